@@ -34,13 +34,13 @@ fn print_addr(addr: &Addr) {
 }
 
 fn main() {
-    let mut conn = NetlinkConnection::new();
-    let links = conn.iter_links().unwrap().collect::<Vec<_>>();
+    let mut conn = NetlinkConnection::new().unwrap();
+    let links = conn.iter_links().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
     for link in links {
         print_link(&link);
         for addr in conn.get_link_addrs(None, &link).unwrap() {
             //println!("{:?}", addr.get_ip());
-            print_addr(&addr);
+            print_addr(&addr.unwrap());
         }
     }
 }
@@ -0,0 +1,30 @@
+extern crate pnetlink;
+
+use pnetlink::packet::route::monitor::{RtnetlinkMonitor, RtnetlinkEvent};
+use pnetlink::packet::route::monitor::{RTNLGRP_LINK, RTNLGRP_IPV4_IFADDR, RTNLGRP_IPV6_IFADDR,
+                                       RTNLGRP_IPV4_ROUTE, RTNLGRP_IPV6_ROUTE, RTNLGRP_NEIGH,
+                                       RTNLGRP_IPV4_RULE, RTNLGRP_IPV6_RULE};
+
+fn main() {
+    let monitor = RtnetlinkMonitor::new(&[RTNLGRP_LINK, RTNLGRP_IPV4_IFADDR, RTNLGRP_IPV6_IFADDR,
+                                          RTNLGRP_IPV4_ROUTE, RTNLGRP_IPV6_ROUTE, RTNLGRP_NEIGH,
+                                          RTNLGRP_IPV4_RULE, RTNLGRP_IPV6_RULE])
+        .unwrap();
+    for event in monitor {
+        match event.unwrap() {
+            RtnetlinkEvent::NewLink(link) => println!("link {:?}", link),
+            RtnetlinkEvent::DelLink(link) => println!("Deleted link {:?}", link),
+            RtnetlinkEvent::NewAddr(addr) => println!("addr {:?} dev {}", addr.get_ip(), addr.get_link_index()),
+            RtnetlinkEvent::DelAddr(addr) => println!("Deleted addr {:?} dev {}", addr.get_ip(), addr.get_link_index()),
+            RtnetlinkEvent::NewRoute(route) => println!("route {:?}", route),
+            RtnetlinkEvent::DelRoute(route) => println!("Deleted route {:?}", route),
+            RtnetlinkEvent::NewNeighbour(neighbour) => println!("neigh {:?}", neighbour),
+            RtnetlinkEvent::DelNeighbour(neighbour) => println!("Deleted neigh {:?}", neighbour),
+            RtnetlinkEvent::NewRule(rule) => println!("rule {:?}", rule),
+            RtnetlinkEvent::DelRule(rule) => println!("Deleted rule {:?}", rule),
+            RtnetlinkEvent::NewNexthop(nexthop) => println!("nexthop {:?}", nexthop),
+            RtnetlinkEvent::DelNexthop(nexthop) => println!("Deleted nexthop {:?}", nexthop),
+            RtnetlinkEvent::Other(pkt) => println!("{:?}", pkt),
+        }
+    }
+}
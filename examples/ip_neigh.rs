@@ -7,10 +7,13 @@ use pnetlink::packet::route::link::{Links, Link};
 use pnetlink::packet::route::neighbour::{Neighbour, Neighbours, NOARP};
 
 fn main() {
-    let mut conn = NetlinkConnection::new();
-    let links =
-        conn.iter_links().unwrap().map(|link| (link.get_index(), link)).collect::<HashMap<_, _>>();
-    let neighbours = conn.iter_neighbours(None).unwrap().collect::<Vec<_>>();
+    let mut conn = NetlinkConnection::new().unwrap();
+    let links = conn.iter_links()
+        .unwrap()
+        .map(|link| link.unwrap())
+        .map(|link| (link.get_index(), link))
+        .collect::<HashMap<_, _>>();
+    let neighbours = conn.iter_neighbours(None).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
     for neighbour in neighbours {
         if neighbour.get_state() == NOARP {
             continue;
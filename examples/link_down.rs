@@ -6,7 +6,7 @@ use pnetlink::packet::route::link::Links;
 use std::env;
 
 fn main() {
-    let mut conn = NetlinkConnection::new();
+    let mut conn = NetlinkConnection::new().unwrap();
 
     let linkname = match env::args().nth(1) {
         Some(n) => n,
@@ -0,0 +1,125 @@
+//! Error type returned by netlink operations
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use packet::netlink::{ExtAck,NetlinkPacket};
+
+/// Result of a netlink operation
+pub type Result<T> = result::Result<T, Error>;
+
+/// Netlink error
+#[derive(Debug)]
+pub enum Error {
+    /// Kernel rejected the request with NLMSG_ERROR
+    Kernel(KernelError),
+    /// Input ended in the middle of a message
+    Truncated,
+    /// Message could not be parsed
+    Malformed(&'static str),
+    /// Kernel dropped messages because the socket buffer overflowed (NLMSG_OVERRUN)
+    Overrun,
+    /// Dump was interrupted by a concurrent change and may be
+    /// inconsistent (NLM_F_DUMP_INTR)
+    DumpInterrupted,
+    /// ACK or error for a request that was not sent yet was received
+    UnexpectedAck { expected: u32, received: u32 },
+    /// Request can't be built from the given values
+    InvalidArgument(&'static str),
+    /// Socket I/O error
+    Io(io::Error),
+}
+
+impl Error {
+    /// Returns errno for kernel and OS errors
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match *self {
+            Error::Kernel(ref err) => Some(err.get_errno()),
+            Error::Io(ref err) => err.raw_os_error(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Kernel(ref err) => {
+                try!(write!(f, "netlink error: {}", io::Error::from_raw_os_error(err.get_errno())));
+                match err.get_ext_ack().and_then(|ack| ack.get_msg()) {
+                    Some(msg) => write!(f, " ({})", msg),
+                    None => Ok(()),
+                }
+            },
+            Error::Malformed(what) => write!(f, "malformed netlink message: {}", what),
+            Error::InvalidArgument(what) => write!(f, "invalid argument: {}", what),
+            Error::UnexpectedAck { expected, received } => {
+                write!(f, "netlink ACK for sequence {} received while waiting for {}", received, expected)
+            },
+            Error::Io(ref err) => write!(f, "netlink socket error: {}", err),
+            _ => write!(f, "{}", error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Kernel(_) => "netlink request failed",
+            Error::Truncated => "truncated netlink message",
+            Error::Malformed(_) => "malformed netlink message",
+            Error::Overrun => "netlink overrun, messages were lost",
+            Error::DumpInterrupted => "netlink dump was interrupted, results may be inconsistent",
+            Error::UnexpectedAck { .. } => "netlink ACK for another request",
+            Error::InvalidArgument(_) => "invalid argument",
+            Error::Io(ref err) => error::Error::description(err),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// Error reported by the kernel in NLMSG_ERROR reply
+#[derive(Debug)]
+pub struct KernelError {
+    errno: i32,
+    header: NetlinkPacket<'static>,
+    ext_ack: Option<ExtAck>,
+}
+
+impl KernelError {
+    pub fn new(errno: i32, header: NetlinkPacket<'static>, ext_ack: Option<ExtAck>) -> Self {
+        KernelError {
+            errno: errno,
+            header: header,
+            ext_ack: ext_ack,
+        }
+    }
+
+    /// Get error number (positive errno value)
+    pub fn get_errno(&self) -> i32 {
+        self.errno
+    }
+
+    /// Get header of the failed request as echoed by the kernel
+    pub fn get_header(&self) -> &NetlinkPacket<'static> {
+        &self.header
+    }
+
+    /// Get extended ACK attributes, only sent if `SockOpt::ExtAck` is enabled
+    pub fn get_ext_ack(&self) -> Option<&ExtAck> {
+        self.ext_ack.as_ref()
+    }
+}
@@ -3,13 +3,26 @@
 //!
 //! `socket` module can be used to establish Netlink socket
 //! `packet` contains high level functions and traits
+//! `error` defines the error type returned by them
+//!
+//! With `async` feature enabled `packet::async_netlink` provides
+//! a connection driven by tokio reactor
 #[macro_use]
 extern crate bitflags; 
 extern crate pnet;
 extern crate pnet_macros_support;
 extern crate libc;
 extern crate byteorder;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "async")]
+extern crate tokio_core;
+#[cfg(feature = "mio")]
+extern crate mio;
 
 pub mod socket;
 pub mod packet;
 pub mod util;
+pub mod error;
+
+pub use error::{Error, Result};
@@ -0,0 +1,388 @@
+//! Netlink connection driven by tokio reactor
+//!
+//! Requests are sent right away and their replies are returned as a
+//! stream. Any number of requests may be in flight on one socket,
+//! replies are routed to them by sequence number. The kernel runs only
+//! one dump per socket at a time, so dump requests are queued and sent
+//! once the previous dump is finished.
+//!
+//! # Example
+//! ```ignore
+//! extern crate futures;
+//! extern crate tokio_core;
+//! extern crate pnetlink;
+//!
+//! use futures::{Future,Stream};
+//! use tokio_core::reactor::Core;
+//! use pnetlink::packet::async_netlink::AsyncNetlinkConnection;
+//! use pnetlink::packet::route::link::AsyncLinks;
+//! use pnetlink::packet::route::addr::AsyncAddresses;
+//!
+//! let mut core = Core::new().unwrap();
+//! let conn = AsyncNetlinkConnection::new(&core.handle()).unwrap();
+//! let links = conn.iter_links().collect();
+//! let addrs = conn.iter_addrs(None).collect();
+//! let (links, addrs) = core.run(links.join(addrs)).unwrap();
+//! ```
+use std::cell::RefCell;
+use std::collections::{HashMap,VecDeque};
+use std::io::{self,Read};
+use std::rc::Rc;
+use std::cmp;
+
+use futures::{Async,Future,Poll,Stream};
+use futures::stream;
+use futures::task::{self,Task};
+use tokio_core::reactor::{Handle,PollEvented};
+use pnet::packet::Packet;
+
+use ::error::{Error,Result};
+use ::socket::{NetlinkSocket,NetlinkProtocol,SOCK_NONBLOCK,SOCK_CLOEXEC};
+use packet::netlink::{MutableNetlinkPacket,NetlinkPacket,NetlinkReader};
+use packet::netlink::{NLM_F_DUMP,NLMSG_DONE,NLMSG_ERROR};
+
+/// Replies received for a request but not consumed yet
+struct Pending {
+    buf: Vec<u8>,
+    // malformed message was received, nothing follows
+    eof: bool,
+    error: Option<io::Error>,
+    task: Option<Task>,
+}
+
+struct Inner {
+    io: PollEvented<NetlinkSocket>,
+    port_id: u32,
+    seq: u32,
+    pending: HashMap<u32, Pending>,
+    rbuf: Vec<u8>,
+    // dump in progress and dumps waiting for it to finish
+    dump_seq: Option<u32>,
+    dumps: VecDeque<(u32, Vec<u8>)>,
+}
+
+impl Inner {
+    /// Sequence numbers start at 1, 0 is used by kernel notifications
+    fn next_seq(&mut self) -> u32 {
+        loop {
+            self.seq = self.seq.wrapping_add(1);
+            if self.seq != 0 && !self.pending.contains_key(&self.seq) {
+                return self.seq;
+            }
+        }
+    }
+
+    /// Sends request `seq`, a dump is queued if another one is running
+    fn send_request(&mut self, seq: u32, data: Vec<u8>, dump: bool) -> io::Result<()> {
+        if dump {
+            if self.dump_seq.is_some() {
+                self.dumps.push_back((seq, data));
+                return Ok(());
+            }
+            self.dump_seq = Some(seq);
+        }
+        match self.io.get_mut().send(&data) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                if dump {
+                    self.dump_seq = None;
+                }
+                Err(e)
+            },
+        }
+    }
+
+    /// Sends the next queued dump whose response is still alive
+    fn dump_done(&mut self) {
+        self.dump_seq = None;
+        while let Some((seq, data)) = self.dumps.pop_front() {
+            if !self.pending.contains_key(&seq) {
+                continue;
+            }
+            match self.send_request(seq, data, true) {
+                Ok(()) => return,
+                Err(e) => {
+                    let pending = self.pending.get_mut(&seq).unwrap();
+                    pending.error = Some(e);
+                    if let Some(task) = pending.task.take() {
+                        task.notify();
+                    }
+                },
+            }
+        }
+    }
+
+    /// Reads queued datagrams and routes messages to pending requests.
+    /// Socket errors are passed to every pending request.
+    fn poll_socket(&mut self) {
+        loop {
+            if let Async::NotReady = self.io.poll_read() {
+                return;
+            }
+            let res = self.io.get_mut().peek_size().and_then(|size| {
+                self.rbuf.resize(size, 0);
+                self.io.get_mut().recv(&mut self.rbuf)
+            });
+            match res {
+                Ok(len) => self.dispatch(len),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.io.need_read();
+                    return;
+                },
+                Err(e) => {
+                    for pending in self.pending.values_mut() {
+                        pending.error = Some(match e.raw_os_error() {
+                            Some(errno) => io::Error::from_raw_os_error(errno),
+                            None => io::Error::new(e.kind(), "netlink socket error"),
+                        });
+                    }
+                    // queued dumps failed as well
+                    self.dump_seq = None;
+                    self.dumps.clear();
+                    self.wake_all();
+                    return;
+                },
+            }
+        }
+    }
+
+    /// Splits datagram of `len` bytes into messages and queues them
+    fn dispatch(&mut self, len: usize) {
+        let mut at = 0;
+        while at < len {
+            let (pkt_len, seq, pid, kind) = match NetlinkPacket::new(&self.rbuf[at..len]) {
+                Some(pkt) => (pkt.get_length() as usize, pkt.get_seq(), pkt.get_pid(), pkt.get_kind()),
+                None => (len - at, 0, 0, 0),
+            };
+            // pass the rest to the reader, it reports the error
+            let malformed = pkt_len < NetlinkPacket::minimum_packet_size() || at + pkt_len > len;
+            let end = if malformed { len } else { at + pkt_len };
+            if pid == self.port_id {
+                if let Some(pending) = self.pending.get_mut(&seq) {
+                    pending.buf.extend_from_slice(&self.rbuf[at..end]);
+                    let aligned = ::util::align(pending.buf.len());
+                    pending.buf.resize(aligned, 0);
+                    pending.eof |= malformed;
+                    if let Some(task) = pending.task.take() {
+                        task.notify();
+                    }
+                }
+                // the dump ends even if its response was dropped
+                if self.dump_seq == Some(seq) && (malformed || kind == NLMSG_DONE || kind == NLMSG_ERROR) {
+                    self.dump_done();
+                }
+            }
+            at = cmp::min(::util::align(end), len);
+        }
+    }
+
+    /// Lets waiting requests poll again, one of them will take over
+    /// reading the socket
+    fn wake_all(&mut self) {
+        for pending in self.pending.values_mut() {
+            if let Some(task) = pending.task.take() {
+                task.notify();
+            }
+        }
+    }
+}
+
+/// Netlink connection registered with tokio reactor. Cloned handles
+/// share the socket.
+#[derive(Clone)]
+pub struct AsyncNetlinkConnection {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl AsyncNetlinkConnection {
+    /// Opens NETLINK_ROUTE connection driven by reactor `handle`
+    pub fn new(handle: &Handle) -> Result<Self> {
+        let sock = try!(NetlinkSocket::bind_with_flags(NetlinkProtocol::Route, 0, 0, SOCK_NONBLOCK | SOCK_CLOEXEC));
+        AsyncNetlinkConnection::from_socket(sock, handle)
+    }
+
+    /// Registers bound socket `sock` with reactor `handle`
+    pub fn from_socket(mut sock: NetlinkSocket, handle: &Handle) -> Result<Self> {
+        try!(sock.set_nonblocking(true));
+        let port_id = sock.get_port_id();
+        let io = try!(PollEvented::new(sock, handle));
+        Ok(AsyncNetlinkConnection {
+            inner: Rc::new(RefCell::new(Inner {
+                io: io,
+                port_id: port_id,
+                seq: 0,
+                pending: HashMap::new(),
+                rbuf: vec![],
+                dump_seq: None,
+                dumps: VecDeque::new(),
+            })),
+        })
+    }
+
+    /// Sends `msg` with the next sequence number and returns
+    /// stream of the replies to it. A dump is sent after the running
+    /// one, if any, is finished.
+    pub fn send(&self, msg: NetlinkPacket) -> Result<NetlinkResponse> {
+        let mut inner = self.inner.borrow_mut();
+        let seq = inner.next_seq();
+        let mut data = msg.packet().to_owned();
+        {
+            let mut pkt = match MutableNetlinkPacket::new(&mut data) {
+                Some(pkt) => pkt,
+                None => return Err(Error::Malformed("request is shorter than netlink header")),
+            };
+            pkt.set_seq(seq);
+        }
+        let dump = msg.get_flags().contains(NLM_F_DUMP);
+        inner.pending.insert(seq, Pending { buf: vec![], eof: false, error: None, task: None });
+        if let Err(e) = inner.send_request(seq, data, dump) {
+            inner.pending.remove(&seq);
+            return Err(Error::Io(e));
+        }
+        let reader = PendingReader { inner: self.inner.clone(), seq: seq };
+        Ok(NetlinkResponse {
+            inner: self.inner.clone(),
+            seq: seq,
+            reader: NetlinkReader::with_seq(reader, inner.port_id, seq),
+        })
+    }
+
+    /// Same as `send`, but failure to send is returned by the stream
+    pub fn request(&self, msg: NetlinkPacket) -> Box<Stream<Item = NetlinkPacket<'static>, Error = Error>> {
+        match self.send(msg) {
+            Ok(response) => Box::new(response),
+            Err(e) => Box::new(stream::once(Err(e))),
+        }
+    }
+
+    /// Sends `msg` and waits for ACK, ignoring other replies
+    pub fn request_ack(&self, msg: NetlinkPacket) -> Box<Future<Item = (), Error = Error>> {
+        Box::new(self.request(msg).for_each(|_| Ok(())))
+    }
+}
+
+/// Reads replies routed to request `seq`
+struct PendingReader {
+    inner: Rc<RefCell<Inner>>,
+    seq: u32,
+}
+
+impl Read for PendingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut inner = self.inner.borrow_mut();
+        let pending = match inner.pending.get_mut(&self.seq) {
+            Some(pending) => pending,
+            None => return Ok(0),
+        };
+        if pending.buf.is_empty() {
+            if let Some(e) = pending.error.take() {
+                return Err(e);
+            }
+            if pending.eof {
+                return Ok(0);
+            }
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "no replies received yet"));
+        }
+        let len = cmp::min(buf.len(), pending.buf.len());
+        buf[..len].copy_from_slice(&pending.buf[..len]);
+        pending.buf.drain(..len);
+        Ok(len)
+    }
+}
+
+/// Replies to a request. The stream ends after NLMSG_DONE or ACK,
+/// kernel errors are returned as `Err`.
+pub struct NetlinkResponse {
+    inner: Rc<RefCell<Inner>>,
+    seq: u32,
+    reader: NetlinkReader<PendingReader>,
+}
+
+impl NetlinkResponse {
+    /// Sequence number of the request
+    pub fn get_seq(&self) -> u32 {
+        self.seq
+    }
+}
+
+impl Stream for NetlinkResponse {
+    type Item = NetlinkPacket<'static>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            match self.reader.read_netlink() {
+                Ok(Some(pkt)) => return Ok(Async::Ready(Some(pkt))),
+                Ok(None) => {
+                    self.inner.borrow_mut().wake_all();
+                    return Ok(Async::Ready(None));
+                },
+                Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {},
+                Err(e) => {
+                    self.inner.borrow_mut().wake_all();
+                    return Err(e);
+                },
+            }
+            let mut inner = self.inner.borrow_mut();
+            inner.poll_socket();
+            let pending = inner.pending.get_mut(&self.seq).unwrap();
+            if pending.buf.is_empty() && pending.error.is_none() && !pending.eof {
+                pending.task = Some(task::current());
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
+
+impl Drop for NetlinkResponse {
+    fn drop(&mut self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.pending.remove(&self.seq);
+        // this stream may have been the one waiting on the socket
+        inner.wake_all();
+    }
+}
+
+#[test]
+fn concurrent_dumps() {
+    use tokio_core::reactor::Core;
+    use packet::route::link::AsyncLinks;
+    use packet::route::addr::AsyncAddresses;
+
+    let mut core = Core::new().unwrap();
+    let conn = AsyncNetlinkConnection::new(&core.handle()).unwrap();
+    let links = conn.iter_links().collect();
+    let addrs = conn.iter_addrs(None).collect();
+    let (links, addrs) = core.run(links.join(addrs)).unwrap();
+    let lo = links.iter().find(|link| link.get_name() == Some("lo".to_owned())).unwrap();
+    assert!(addrs.iter().any(|addr| addr.get_link_index() == lo.get_index()));
+}
+
+#[test]
+fn dumps_are_queued() {
+    use tokio_core::reactor::Core;
+    use packet::route::link::AsyncLinks;
+
+    let mut core = Core::new().unwrap();
+    let conn = AsyncNetlinkConnection::new(&core.handle()).unwrap();
+    // the first dump is dropped before it finishes, the queued ones
+    // must still be sent after its NLMSG_DONE
+    drop(conn.iter_links());
+    let links = conn.iter_links().collect();
+    let more_links = conn.iter_links().collect();
+    let lo = conn.get_link_by_name("lo");
+    let (links, more_links, lo) = core.run(links.join3(more_links, lo)).unwrap();
+    let lo = lo.unwrap();
+    assert!(links.iter().any(|link| link.get_index() == lo.get_index()));
+    assert!(more_links.iter().any(|link| link.get_index() == lo.get_index()));
+}
+
+#[test]
+fn get_missing_link() {
+    use tokio_core::reactor::Core;
+    use packet::route::link::AsyncLinks;
+
+    let mut core = Core::new().unwrap();
+    let conn = AsyncNetlinkConnection::new(&core.handle()).unwrap();
+    assert!(core.run(conn.get_link_by_index(0x7fffffff)).unwrap().is_none());
+}
@@ -4,6 +4,7 @@ use packet::netlink::{NLMSG_NOOP,NLMSG_ERROR,NLMSG_DONE,NLMSG_OVERRUN};
 use packet::netlink::{NetlinkBufIterator,NetlinkReader,NetlinkRequestBuilder};
 use ::socket::{NetlinkSocket,NetlinkProtocol};
 use packet::netlink::NetlinkConnection;
+use ::error::Result;
 use pnet::packet::MutablePacket;
 use pnet::packet::Packet;
 use pnet::packet::PacketSize;
@@ -13,11 +14,11 @@ use std::io::{Read,Write};
 include!(concat!(env!("OUT_DIR"), "/audit/audit.rs"));
 
 pub trait Audit where Self: Read + Write {
-    fn audit_enable<'a>(&'a mut self) -> ::std::io::Result<()>;
+    fn audit_enable<'a>(&'a mut self) -> Result<()>;
 }
 
 impl Audit for NetlinkConnection {
-    fn audit_enable<'a>(&'a mut self) -> ::std::io::Result<()> {
+    fn audit_enable<'a>(&'a mut self) -> Result<()> {
         let mut buf = vec![0; MutableAuditStatusPacket::minimum_packet_size()];
         let req = NetlinkRequestBuilder::new(1001, NLM_F_REQUEST | NLM_F_ACK)
             .append({
@@ -26,8 +27,7 @@ impl Audit for NetlinkConnection {
                 status.set_enabled(1);
                 status
             }).build();
-        try!(self.write(req.packet()));
-        let reader = NetlinkReader::new(self);
+        let reader = try!(self.send(req));
         reader.read_to_end()
     }
 }
@@ -2,3 +2,5 @@
 pub mod netlink;
 pub mod route;
 pub mod audit;
+#[cfg(feature = "async")]
+pub mod async_netlink;
@@ -1,11 +1,19 @@
 //! Netlink packet handling
 use ::socket::{NetlinkSocket,NetlinkProtocol};
+use ::error::{Error,KernelError,Result};
+use ::socket::SockOpt;
+use packet::route::RtAttrIterator;
 use libc;
+use std::cmp;
 use std::mem;
 use std::io;
 use std::io::{Read,BufRead,BufReader,Write};
 use std::marker::PhantomData;
+use std::os::unix::io::{AsRawFd,RawFd};
+#[cfg(feature = "mio")]
+use mio::{Evented,Poll,PollOpt,Ready,Token};
 use pnet::packet::{Packet,PacketSize,FromPacket};
+use byteorder::{ByteOrder,NativeEndian};
 
 include!(concat!(env!("OUT_DIR"), "/netlink.rs"));
 
@@ -33,6 +41,10 @@ bitflags! {
         const NLM_F_EXCL =    0x200,   /* Do not touch, if it exists   */
         const NLM_F_CREATE =  0x400,   /* Create, if it does not exist */
         const NLM_F_APPEND =  0x800,   /* Add to end of list           */
+
+        /* Flags for ACK message */
+        const NLM_F_CAPPED = 0x100,    /* request was capped */
+        const NLM_F_ACK_TLVS = 0x200,  /* extended ACK TLVs were included */
     }
 }
 
@@ -48,6 +60,173 @@ pub const NLMSG_ERROR: u16 = 2;
 pub const NLMSG_DONE: u16 = 3;
 pub const NLMSG_OVERRUN: u16 = 4;
 
+/* extended ACK attributes (NETLINK_EXT_ACK) */
+pub const NLMSGERR_ATTR_UNUSED: u16 = 0;
+pub const NLMSGERR_ATTR_MSG: u16 = 1;
+pub const NLMSGERR_ATTR_OFFS: u16 = 2;
+pub const NLMSGERR_ATTR_COOKIE: u16 = 3;
+pub const NLMSGERR_ATTR_POLICY: u16 = 4;
+
+/* policy attributes nested in NLMSGERR_ATTR_POLICY */
+pub const NL_POLICY_TYPE_ATTR_UNSPEC: u16 = 0;
+pub const NL_POLICY_TYPE_ATTR_TYPE: u16 = 1;
+pub const NL_POLICY_TYPE_ATTR_MIN_VALUE_S: u16 = 2;
+pub const NL_POLICY_TYPE_ATTR_MAX_VALUE_S: u16 = 3;
+pub const NL_POLICY_TYPE_ATTR_MIN_VALUE_U: u16 = 4;
+pub const NL_POLICY_TYPE_ATTR_MAX_VALUE_U: u16 = 5;
+pub const NL_POLICY_TYPE_ATTR_MIN_LENGTH: u16 = 6;
+pub const NL_POLICY_TYPE_ATTR_MAX_LENGTH: u16 = 7;
+pub const NL_POLICY_TYPE_ATTR_POLICY_IDX: u16 = 8;
+pub const NL_POLICY_TYPE_ATTR_POLICY_MAXTYPE: u16 = 9;
+pub const NL_POLICY_TYPE_ATTR_BITFIELD32_MASK: u16 = 10;
+pub const NL_POLICY_TYPE_ATTR_PAD: u16 = 11;
+pub const NL_POLICY_TYPE_ATTR_MASK: u16 = 12;
+
+/* attribute type without NLA_F_NESTED and NLA_F_NET_BYTEORDER bits */
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+/// Extended ACK information sent by the kernel along with NLMSG_ERROR
+/// when NETLINK_EXT_ACK socket option is enabled
+#[derive(Debug,Default)]
+pub struct ExtAck {
+    msg: Option<String>,
+    offset: Option<u32>,
+    attr_type: Option<u16>,
+    cookie: Option<Vec<u8>>,
+    policy: Option<ExtAckPolicy>,
+}
+
+impl ExtAck {
+    /// Decodes extended ACK attributes from NLMSG_ERROR message
+    pub fn from_error_packet(pkt: &NetlinkPacket) -> Option<ExtAck> {
+        if pkt.get_kind() != NLMSG_ERROR || !pkt.get_flags().contains(NLM_F_ACK_TLVS) {
+            return None;
+        }
+        let payload = pkt.payload();
+        if payload.len() < 4 {
+            return None;
+        }
+        let (request, attrs) = split_echo(pkt.get_flags(), &payload[4..]);
+        let request = if pkt.get_flags().contains(NLM_F_CAPPED) { None } else { Some(request) };
+        let mut ack = ExtAck::default();
+        for rta in RtAttrIterator::new(attrs) {
+            let payload = rta.payload();
+            match rta.get_rta_type() & NLA_TYPE_MASK {
+                NLMSGERR_ATTR_MSG => {
+                    let len = payload.iter().position(|b| *b == 0).unwrap_or(payload.len());
+                    ack.msg = Some(String::from_utf8_lossy(&payload[..len]).into_owned());
+                },
+                NLMSGERR_ATTR_OFFS if payload.len() >= 4 => {
+                    let offset = NativeEndian::read_u32(payload);
+                    ack.offset = Some(offset);
+                    ack.attr_type = request.and_then(|req| attr_type_at(req, offset));
+                },
+                NLMSGERR_ATTR_COOKIE => {
+                    ack.cookie = Some(payload.to_owned());
+                },
+                NLMSGERR_ATTR_POLICY => {
+                    ack.policy = Some(ExtAckPolicy::new(payload));
+                },
+                _ => {},
+            }
+        }
+        Some(ack)
+    }
+
+    /// Get human-readable error message
+    pub fn get_msg(&self) -> Option<&str> {
+        self.msg.as_ref().map(|msg| &msg[..])
+    }
+
+    /// Get offset of the offending attribute in the request
+    pub fn get_offset(&self) -> Option<u32> {
+        self.offset
+    }
+
+    /// Get type of the offending attribute. It's only known if the
+    /// kernel echoed the whole request, see `get_attr_type_in` otherwise.
+    pub fn get_attr_type(&self) -> Option<u16> {
+        self.attr_type
+    }
+
+    /// Get type of the offending attribute in the original `request`
+    pub fn get_attr_type_in(&self, request: &[u8]) -> Option<u16> {
+        self.offset.and_then(|offset| attr_type_at(request, offset))
+    }
+
+    /// Get cookie
+    pub fn get_cookie(&self) -> Option<&[u8]> {
+        self.cookie.as_ref().map(|cookie| &cookie[..])
+    }
+
+    /// Get policy the offending attribute failed to match
+    pub fn get_policy(&self) -> Option<&ExtAckPolicy> {
+        self.policy.as_ref()
+    }
+}
+
+/// Attribute policy reported in NLMSGERR_ATTR_POLICY
+#[derive(Debug,Default)]
+pub struct ExtAckPolicy {
+    /// Expected attribute type (`enum netlink_attribute_type`)
+    pub type_: Option<u32>,
+    pub min_value_s: Option<i64>,
+    pub max_value_s: Option<i64>,
+    pub min_value_u: Option<u64>,
+    pub max_value_u: Option<u64>,
+    pub min_length: Option<u32>,
+    pub max_length: Option<u32>,
+    pub mask: Option<u64>,
+}
+
+impl ExtAckPolicy {
+    fn new(buf: &[u8]) -> Self {
+        let mut policy = ExtAckPolicy::default();
+        for rta in RtAttrIterator::new(buf) {
+            let payload = rta.payload();
+            match (rta.get_rta_type() & NLA_TYPE_MASK, payload.len()) {
+                (NL_POLICY_TYPE_ATTR_TYPE, 4) => policy.type_ = Some(NativeEndian::read_u32(payload)),
+                (NL_POLICY_TYPE_ATTR_MIN_VALUE_S, 8) => policy.min_value_s = Some(NativeEndian::read_i64(payload)),
+                (NL_POLICY_TYPE_ATTR_MAX_VALUE_S, 8) => policy.max_value_s = Some(NativeEndian::read_i64(payload)),
+                (NL_POLICY_TYPE_ATTR_MIN_VALUE_U, 8) => policy.min_value_u = Some(NativeEndian::read_u64(payload)),
+                (NL_POLICY_TYPE_ATTR_MAX_VALUE_U, 8) => policy.max_value_u = Some(NativeEndian::read_u64(payload)),
+                (NL_POLICY_TYPE_ATTR_MIN_LENGTH, 4) => policy.min_length = Some(NativeEndian::read_u32(payload)),
+                (NL_POLICY_TYPE_ATTR_MAX_LENGTH, 4) => policy.max_length = Some(NativeEndian::read_u32(payload)),
+                (NL_POLICY_TYPE_ATTR_MASK, 8) => policy.mask = Some(NativeEndian::read_u64(payload)),
+                _ => {},
+            }
+        }
+        policy
+    }
+}
+
+/// Splits NLMSG_ERROR payload following the error code into
+/// echoed request and extended ACK attributes
+fn split_echo(flags: NetlinkMsgFlags, echo: &[u8]) -> (&[u8], &[u8]) {
+    let len = match NetlinkPacket::new(echo) {
+        Some(hdr) => {
+            if flags.contains(NLM_F_CAPPED) {
+                NetlinkPacket::minimum_packet_size()
+            } else {
+                hdr.get_length() as usize
+            }
+        },
+        None => echo.len(),
+    };
+    let len = cmp::min(len, echo.len());
+    let attrs_at = cmp::min(::util::align(len), echo.len());
+    (&echo[..len], &echo[attrs_at..])
+}
+
+/// Returns type of the attribute starting at `offset` in `request`
+fn attr_type_at(request: &[u8], offset: u32) -> Option<u16> {
+    let offset = offset as usize;
+    if offset + 4 > request.len() {
+        return None;
+    }
+    Some(NativeEndian::read_u16(&request[offset + 2..]) & NLA_TYPE_MASK)
+}
+
 
 impl<'a> NetlinkIterable<'a> {
     pub fn new(buf: &'a [u8]) -> Self {
@@ -108,12 +287,240 @@ fn read_ip_link_sock() {
     }
 }
 
+#[test]
+fn read_ext_ack() {
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    // RTM_NEWLINK request with ifinfomsg and IFLA_MTU attribute
+    let mut req = vec![];
+    req.write_u32::<LittleEndian>(40).unwrap();
+    req.write_u16::<LittleEndian>(16).unwrap();
+    req.write_u16::<LittleEndian>((NLM_F_REQUEST | NLM_F_ACK).bits()).unwrap();
+    req.write_u32::<LittleEndian>(1).unwrap();
+    req.write_u32::<LittleEndian>(0).unwrap();
+    req.extend_from_slice(&[0; 16]);
+    req.write_u16::<LittleEndian>(8).unwrap();
+    req.write_u16::<LittleEndian>(4).unwrap();
+    req.write_u32::<LittleEndian>(1).unwrap();
+
+    let mut msg = vec![];
+    msg.write_u32::<LittleEndian>(80).unwrap();
+    msg.write_u16::<LittleEndian>(NLMSG_ERROR).unwrap();
+    msg.write_u16::<LittleEndian>(NLM_F_ACK_TLVS.bits()).unwrap();
+    msg.write_u32::<LittleEndian>(1).unwrap();
+    msg.write_u32::<LittleEndian>(0).unwrap();
+    msg.write_i32::<LittleEndian>(-22 /* EINVAL */).unwrap();
+    msg.extend_from_slice(&req);
+    msg.write_u16::<LittleEndian>(12).unwrap();
+    msg.write_u16::<LittleEndian>(NLMSGERR_ATTR_MSG).unwrap();
+    msg.extend_from_slice(b"bad mtu\0");
+    msg.write_u16::<LittleEndian>(8).unwrap();
+    msg.write_u16::<LittleEndian>(NLMSGERR_ATTR_OFFS).unwrap();
+    msg.write_u32::<LittleEndian>(32).unwrap();
+
+    let mut reader = NetlinkReader::new(&msg[..]);
+    match reader.read_netlink() {
+        Err(Error::Kernel(err)) => {
+            assert_eq!(err.get_errno(), 22);
+            assert_eq!(err.get_header().get_kind(), 16);
+            let ack = err.get_ext_ack().unwrap();
+            assert_eq!(ack.get_msg(), Some("bad mtu"));
+            assert_eq!(ack.get_offset(), Some(32));
+            assert_eq!(ack.get_attr_type(), Some(4 /* IFLA_MTU */));
+        },
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[cfg(test)]
+fn test_message(kind: u16, flags: NetlinkMsgFlags, seq: u32, pid: u32, payload: &[u8]) -> Vec<u8> {
+    let len = MutableNetlinkPacket::minimum_packet_size() + payload.len();
+    let mut data = vec![0; ::util::align(len)];
+    {
+        let mut pkt = MutableNetlinkPacket::new(&mut data).unwrap();
+        pkt.set_length(len as u32);
+        pkt.set_kind(kind);
+        pkt.set_flags(flags);
+        pkt.set_seq(seq);
+        pkt.set_pid(pid);
+    }
+    data[16..len].copy_from_slice(payload);
+    data
+}
+
+#[test]
+fn read_skips_unrelated_messages() {
+    let mut data = vec![];
+    // notification
+    data.extend_from_slice(&test_message(16, NetlinkMsgFlags::new(0), 0, 0, &[0; 16]));
+    // reply to another request
+    data.extend_from_slice(&test_message(16, NLM_F_MULTI, 4, 100, &[0; 16]));
+    data.extend_from_slice(&test_message(16, NLM_F_MULTI, 5, 100, &[0; 16]));
+    data.extend_from_slice(&test_message(NLMSG_DONE, NLM_F_MULTI, 5, 100, &[0; 4]));
+
+    let mut reader = NetlinkReader::with_seq(&data[..], 100, 5);
+    let pkt = reader.read_netlink().unwrap().unwrap();
+    assert_eq!(pkt.get_kind(), 16);
+    assert_eq!(pkt.get_seq(), 5);
+    let pkt = reader.read_netlink().unwrap().unwrap();
+    assert_eq!(pkt.get_kind(), NLMSG_DONE);
+    assert!(reader.read_netlink().unwrap().is_none());
+}
+
+#[test]
+fn read_interrupted_dump() {
+    let mut data = vec![];
+    data.extend_from_slice(&test_message(16, NLM_F_MULTI, 5, 100, &[0; 16]));
+    data.extend_from_slice(&test_message(16, NLM_F_MULTI | NLM_F_DUMP_INTR, 5, 100, &[0; 16]));
+    data.extend_from_slice(&test_message(NLMSG_DONE, NLM_F_MULTI, 5, 100, &[0; 4]));
+
+    let reader = NetlinkReader::with_seq(&data[..], 100, 5);
+    match reader.into_iter().collect::<Result<Vec<_>>>() {
+        Err(Error::DumpInterrupted) => {},
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn read_links_with_small_reads() {
+    use packet::route::link::{IfInfoPacketBuilder, RTM_GETLINK, RTM_NEWLINK};
+
+    // datagrams larger than read buffer must not be truncated
+    struct SmallReads<R: Read>(R);
+
+    impl<R: Read> Read for SmallReads<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = cmp::min(buf.len(), 100);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    let mut sock = NetlinkSocket::bind(NetlinkProtocol::Route, 0).unwrap();
+    let req = NetlinkRequestBuilder::new(RTM_GETLINK, NLM_F_DUMP)
+        .append(IfInfoPacketBuilder::new().build())
+        .build();
+    sock.send(req.packet()).unwrap();
+    let reader = NetlinkReader::new(SmallReads(&mut sock));
+    let pkts = reader.into_iter().collect::<Result<Vec<_>>>().unwrap();
+    assert!(pkts.iter().any(|pkt| pkt.get_kind() == RTM_NEWLINK));
+    assert_eq!(pkts.last().unwrap().get_kind(), NLMSG_DONE);
+}
+
+#[test]
+fn read_netlink_ref_reuses_buffer() {
+    let mut data = vec![];
+    for _ in 0..10000 {
+        data.extend_from_slice(&test_message(16, NLM_F_MULTI, 5, 100, &[0; 16]));
+    }
+    data.extend_from_slice(&test_message(NLMSG_DONE, NLM_F_MULTI, 5, 100, &[0; 4]));
+
+    let mut reader = NetlinkReader::with_seq(&data[..], 100, 5);
+    let mut count = 0;
+    while let Some(pkt) = reader.read_netlink_ref().unwrap() {
+        if pkt.get_kind() == 16 {
+            count += 1;
+        }
+    }
+    assert_eq!(count, 10000);
+    assert!(reader.buffer_capacity() < data.len());
+}
+
+#[test]
+fn read_large_message() {
+    // spans several reads and is completed without compacting the buffer
+    let mut data = test_message(16, NLM_F_MULTI, 5, 100, &[0; 100000]);
+    data.extend_from_slice(&test_message(16, NLM_F_MULTI, 5, 100, &[0; 16]));
+    data.extend_from_slice(&test_message(NLMSG_DONE, NLM_F_MULTI, 5, 100, &[0; 4]));
+
+    let mut reader = NetlinkReader::with_seq(&data[..], 100, 5);
+    assert_eq!(reader.read_netlink().unwrap().unwrap().get_length(), 100016);
+    assert_eq!(reader.read_netlink().unwrap().unwrap().get_length(), 32);
+    assert_eq!(reader.read_netlink().unwrap().unwrap().get_kind(), NLMSG_DONE);
+    assert!(reader.read_netlink().unwrap().is_none());
+}
+
+#[test]
+fn read_netlink_ref_skipping() {
+    let mut data = vec![];
+    for kind in 16..20 {
+        data.extend_from_slice(&test_message(kind, NLM_F_MULTI, 5, 100, &[0; 16]));
+    }
+    data.extend_from_slice(&test_message(NLMSG_DONE, NLM_F_MULTI, 5, 100, &[0; 4]));
+
+    let mut reader = NetlinkReader::with_seq(&data[..], 100, 5);
+    let mut kinds = vec![];
+    while let Some(pkt) = reader.read_netlink_ref_skipping(|pkt| pkt.get_kind() == 17 || pkt.get_kind() == 19).unwrap() {
+        kinds.push(pkt.get_kind());
+    }
+    assert_eq!(kinds, vec![16, 18, NLMSG_DONE]);
+}
+
+#[test]
+fn read_would_block() {
+    use ::socket::SOCK_NONBLOCK;
+    use packet::route::link::{IfInfoPacketBuilder, RTM_GETLINK};
+
+    let sock = NetlinkSocket::bind_with_flags(NetlinkProtocol::Route, 0, 0, SOCK_NONBLOCK).unwrap();
+    let mut conn = NetlinkConnection::from(sock);
+    {
+        let mut reader = NetlinkReader::new(&mut conn);
+        match reader.read_netlink() {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+    let req = NetlinkRequestBuilder::new(RTM_GETLINK, NLM_F_DUMP)
+        .append(IfInfoPacketBuilder::new().build())
+        .build();
+    let mut reader = conn.send(req).unwrap();
+    let mut count = 0;
+    loop {
+        match reader.read_netlink() {
+            Ok(Some(_)) => count += 1,
+            Ok(None) => break,
+            // kernel queues the next part of the dump on each recv
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+    assert!(count > 0);
+}
+
+#[test]
+fn read_unexpected_ack() {
+    let data = test_message(NLMSG_ERROR, NetlinkMsgFlags::new(0), 6, 100, &[0; 20]);
+    let mut reader = NetlinkReader::with_seq(&data[..], 100, 5);
+    match reader.read_netlink() {
+        Err(Error::UnexpectedAck { expected: 5, received: 6 }) => {},
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn skip_stale_ack() {
+    let mut data = test_message(NLMSG_ERROR, NetlinkMsgFlags::new(0), 4, 100, &[0; 20]);
+    // sequence numbers wrap around
+    data.extend_from_slice(&test_message(NLMSG_ERROR, NetlinkMsgFlags::new(0), u32::max_value(), 100, &[0; 20]));
+    data.extend_from_slice(&test_message(16, NetlinkMsgFlags::new(0), 5, 100, &[0; 16]));
+    let mut reader = NetlinkReader::with_seq(&data[..], 100, 5);
+    assert_eq!(reader.read_netlink().unwrap().unwrap().get_seq(), 5);
+}
+
+/// Size of a single read, messages larger than that are
+/// assembled from several reads
+const RECV_BUF_SIZE: usize = 32768;
+
+/// Largest incomplete message moved to the start of the buffer on refill
+const COMPACT_SIZE: usize = 4096;
+
 /// Netlink packet parser
 pub struct NetlinkReader<R: Read> {
     reader: R,
     buf: Vec<u8>,
     read_at: usize,
     state: NetlinkReaderState,
+    port_id: u32,
+    seq: Option<u32>,
+    dump_intr: bool,
 }
 
 enum NetlinkReaderState {
@@ -130,27 +537,32 @@ impl<R: Read> NetlinkReader<R> {
             buf: vec![],
             read_at: 0,
             state: NetlinkReaderState::NeedMore,
+            port_id: 0,
+            seq: None,
+            dump_intr: false,
+        }
+    }
+
+    /// Creates a reader for replies to request `seq` sent from `port_id`.
+    /// Other messages are discarded, ACK for another request is an error.
+    pub fn with_seq(reader: R, port_id: u32, seq: u32) -> Self {
+        NetlinkReader {
+            port_id: port_id,
+            seq: Some(seq),
+            ..NetlinkReader::new(reader)
         }
     }
 
     /// Read to end ignoring everything but errors
-    pub fn read_to_end(self) -> io::Result<()> {
-        for pkt in self.into_iter() {
-            if pkt.get_kind() == NLMSG_ERROR {
-                let err = NetlinkErrorPacket::new(pkt.payload()).unwrap();
-                if err.get_error() != 0 {
-                    return Err(io::Error::from_raw_os_error(-(err.get_error() as i32)));
-                } else {
-                    break; // ACK
-                }
-            }
+    pub fn read_to_end(mut self) -> Result<()> {
+        while let Some(_) = try!(self.read_netlink()) {
         }
         Ok(())
     }
 }
 
 impl<R: Read> ::std::iter::IntoIterator for NetlinkReader<R> {
-    type Item = NetlinkPacket<'static>;
+    type Item = Result<NetlinkPacket<'static>>;
     type IntoIter = NetlinkBufIterator<R>;
 
     fn into_iter(self) -> Self::IntoIter {
@@ -159,22 +571,85 @@ impl<R: Read> ::std::iter::IntoIterator for NetlinkReader<R> {
 }
 
 impl<R: Read> NetlinkReader<R> {
-    pub fn read_netlink(&mut self) -> io::Result<Option<NetlinkPacket<'static>>> {
+    /// Read next message. Kernel errors, overruns and malformed
+    /// messages are returned as `Err`, ACK is returned as NLMSG_ERROR
+    /// packet with zero error code and ends the stream.
+    /// If any message of a dump had NLM_F_DUMP_INTR set,
+    /// `Error::DumpInterrupted` is returned instead of NLMSG_DONE.
+    /// `WouldBlock` from a non-blocking reader doesn't end the stream,
+    /// reading can be retried once more data is available.
+    pub fn read_netlink(&mut self) -> Result<Option<NetlinkPacket<'static>>> {
+        let pkt = try!(self.read_netlink_ref());
+        Ok(pkt.and_then(|pkt| NetlinkPacket::owned(pkt.packet().to_owned())))
+    }
+
+    /// Same as `read_netlink`, but the message is borrowed from the
+    /// reader's buffer instead of being copied. The buffer is reused,
+    /// so memory use doesn't depend on the size of the dump.
+    ///
+    /// ```ignore
+    /// while let Some(pkt) = try!(reader.read_netlink_ref()) {
+    ///     println!("{:?}", pkt);
+    /// }
+    /// ```
+    pub fn read_netlink_ref(&mut self) -> Result<Option<NetlinkPacket>> {
+        self.read_netlink_ref_skipping(|_| false)
+    }
+
+    /// Same as `read_netlink_ref`, but messages for which `skip`
+    /// returns true are passed over
+    pub fn read_netlink_ref_skipping<F>(&mut self, skip: F) -> Result<Option<NetlinkPacket>>
+        where F: Fn(&NetlinkPacket) -> bool {
+        loop {
+            match try!(self.next_message()) {
+                Some((start, len)) => {
+                    if skip(&NetlinkPacket::new(&self.buf[start..start + len]).unwrap()) {
+                        continue;
+                    }
+                    return Ok(NetlinkPacket::new(&self.buf[start..start + len]));
+                },
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Capacity of the reader's buffer, it is reused between reads
+    /// and doesn't grow with the size of a dump
+    pub fn buffer_capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Returns offset and length of the next message in `buf`
+    fn next_message(&mut self) -> Result<Option<(usize, usize)>> {
         loop {
             match self.state {
                 NetlinkReaderState::NeedMore => {
-                    let mut buf = [0; 4096];
-                    match self.reader.read(&mut buf) {
+                    // drop consumed messages, the incomplete one is moved to
+                    // the front only if it is small, a large one is completed in place
+                    if self.read_at > 0 && self.buf.len() - self.read_at <= COMPACT_SIZE {
+                        self.buf.drain(..self.read_at);
+                        self.read_at = 0;
+                    }
+                    // read straight into the parse buffer
+                    let len = self.buf.len();
+                    self.buf.resize(len + RECV_BUF_SIZE, 0);
+                    let res = self.reader.read(&mut self.buf[len..]);
+                    self.buf.truncate(len + *res.as_ref().unwrap_or(&0));
+                    match res {
                         Ok(0) => {
                             self.state = NetlinkReaderState::Done;
+                            if self.read_at < self.buf.len() {
+                                return Err(Error::Truncated);
+                            }
                             return Ok(None);
                         },
-                        Ok(len) =>{
-                            self.buf.extend_from_slice(&buf[0..len]);
-                        },
+                        Ok(_) => {},
                         Err(e) => {
-                            self.state = NetlinkReaderState::Error;
-                            return Err(e);
+                            // non-blocking reader has no data yet, reading may be retried
+                            if e.kind() != io::ErrorKind::WouldBlock {
+                                self.state = NetlinkReaderState::Error;
+                            }
+                            return Err(Error::Io(e));
                         }
                     }
                 },
@@ -184,30 +659,79 @@ impl<R: Read> NetlinkReader<R> {
             }
             loop {
                 if let Some(pkt) = NetlinkPacket::new(&self.buf[self.read_at..]) {
-                    let len = ::util::align(pkt.get_length() as usize);
-                    if len == 0 {
-                        return Ok(None);
+                    let pkt_len = pkt.get_length() as usize;
+                    if pkt_len < NetlinkPacket::minimum_packet_size() {
+                        self.state = NetlinkReaderState::Error;
+                        return Err(Error::Malformed("message length is smaller than netlink header"));
+                    }
+                    if self.read_at + pkt_len > self.buf.len() {
+                        // rest of the message is not read yet
+                        self.state = NetlinkReaderState::NeedMore;
+                        break;
+                    }
+                    let len = ::util::align(pkt_len);
+                    if let Some(seq) = self.seq {
+                        if pkt.get_seq() != seq || pkt.get_pid() != self.port_id {
+                            // ACKs of earlier requests, e.g. of an abandoned reader, are
+                            // expected, one for a sequence number not sent yet is not
+                            let stale = (seq.wrapping_sub(pkt.get_seq()) as i32) > 0;
+                            if pkt.get_kind() == NLMSG_ERROR && pkt.get_pid() == self.port_id && !stale {
+                                self.state = NetlinkReaderState::Error;
+                                return Err(Error::UnexpectedAck { expected: seq, received: pkt.get_seq() });
+                            }
+                            // notification or stale reply to another request
+                            self.read_at += len;
+                            continue;
+                        }
+                    }
+                    if pkt.get_flags().contains(NLM_F_DUMP_INTR) {
+                        self.dump_intr = true;
                     }
                     match pkt.get_kind() {
                         NLMSG_ERROR => {
-                            self.state = NetlinkReaderState::Error;
+                            let err = match NetlinkErrorPacket::new(pkt.payload()) {
+                                Some(err) => err,
+                                None => {
+                                    self.state = NetlinkReaderState::Error;
+                                    return Err(Error::Malformed("short NLMSG_ERROR message"));
+                                },
+                            };
+                            let errno = -(err.get_error() as i32);
+                            if errno != 0 {
+                                self.state = NetlinkReaderState::Error;
+                                let (header, _) = split_echo(pkt.get_flags(), err.payload());
+                                if header.len() < NetlinkPacket::minimum_packet_size() {
+                                    return Err(Error::Malformed("short header in NLMSG_ERROR message"));
+                                }
+                                let header = NetlinkPacket::owned(header.to_owned()).unwrap();
+                                let ext_ack = ExtAck::from_error_packet(&pkt);
+                                return Err(Error::Kernel(KernelError::new(errno, header, ext_ack)));
+                            }
+                            // ACK
+                            self.state = NetlinkReaderState::Done;
                         },
                         NLMSG_OVERRUN => {
-                            panic!("overrun!");
+                            self.state = NetlinkReaderState::Error;
+                            return Err(Error::Overrun);
                         },
                         NLMSG_DONE => {
                             self.state = NetlinkReaderState::Done;
+                            if self.dump_intr {
+                                self.read_at += len;
+                                return Err(Error::DumpInterrupted);
+                            }
                         },
                         NLMSG_NOOP => {
-                            println!("noop")
+                            self.read_at += len;
+                            continue;
                         },
                         _ => {
                             self.state = NetlinkReaderState::Parsing;
                         },
                     }
-                    let slot = NetlinkPacket::owned(self.buf[self.read_at..self.read_at + pkt.get_length() as usize].to_owned()).unwrap();
+                    let start = self.read_at;
                     self.read_at += len;
-                    return Ok(Some(slot));
+                    return Ok(Some((start, pkt_len)));
                 } else {
                     self.state = NetlinkReaderState::NeedMore;
                     break;
@@ -221,13 +745,27 @@ pub struct NetlinkBufIterator<R: Read> {
     reader: NetlinkReader<R>,
 }
 
+impl<R: Read> NetlinkBufIterator<R> {
+    /// Same as `next`, but the message is borrowed from the reader's
+    /// buffer and messages for which `skip` returns true are passed over
+    pub fn next_ref_skipping<F>(&mut self, skip: F) -> Option<Result<NetlinkPacket>>
+        where F: Fn(&NetlinkPacket) -> bool {
+        match self.reader.read_netlink_ref_skipping(skip) {
+            Ok(Some(pkt)) => Some(Ok(pkt)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
 impl<R: Read> Iterator for NetlinkBufIterator<R> {
-    type Item = NetlinkPacket<'static>;
+    type Item = Result<NetlinkPacket<'static>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.reader.read_netlink() {
-            Ok(Some(slot)) => Some(slot),
-            _ => None,
+            Ok(Some(slot)) => Some(Ok(slot)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
         }
     }
 }
@@ -235,24 +773,92 @@ impl<R: Read> Iterator for NetlinkBufIterator<R> {
 /// NetlinkConnection represents active netlink connection
 pub struct NetlinkConnection {
     sock: NetlinkSocket,
+    seq: u32,
 }
 
 impl From<NetlinkSocket> for NetlinkConnection {
     fn from(sock: NetlinkSocket) -> Self {
-        NetlinkConnection { sock: sock }
+        NetlinkConnection { sock: sock, seq: 0 }
     }
 }
 
 impl NetlinkConnection {
-    pub fn new() -> Self {
-        NetlinkConnection {
-            sock: NetlinkSocket::bind(NetlinkProtocol::Route, 0 as u32).unwrap(),
+    pub fn new() -> Result<Self> {
+        let sock = try!(NetlinkSocket::bind(NetlinkProtocol::Route, 0 as u32));
+        Ok(NetlinkConnection::from(sock))
+    }
+
+    /// Sends `msg` with the next sequence number and returns
+    /// reader for the replies to it
+    pub fn send<'a,'b>(&'a mut self, msg: NetlinkPacket<'b>) -> Result<NetlinkReader<&'a mut NetlinkConnection>> {
+        let seq = self.next_seq();
+        let mut data = msg.packet().to_owned();
+        {
+            let mut pkt = match MutableNetlinkPacket::new(&mut data) {
+                Some(pkt) => pkt,
+                None => return Err(Error::Malformed("request is shorter than netlink header")),
+            };
+            pkt.set_seq(seq);
         }
+        try!(self.sock.send(&data));
+        let port_id = self.sock.get_port_id();
+        Ok(NetlinkReader::with_seq(self, port_id, seq))
+    }
+
+    /// Sends dump request `msg` and collects the replies. Dump
+    /// interrupted by a concurrent change is restarted up to `retries` times.
+    pub fn dump(&mut self, msg: NetlinkPacket, retries: u32) -> Result<Vec<NetlinkPacket<'static>>> {
+        let mut attempt = 0;
+        loop {
+            let reader = try!(self.send(NetlinkPacket::new(msg.packet()).unwrap()));
+            let res = reader.into_iter()
+                .filter(|pkt| match *pkt {
+                    Ok(ref pkt) => pkt.get_kind() != NLMSG_DONE,
+                    Err(_) => true,
+                })
+                .collect::<Result<Vec<_>>>();
+            match res {
+                Err(Error::DumpInterrupted) if attempt < retries => attempt += 1,
+                res => return res,
+            }
+        }
+    }
+
+    /// Sequence numbers start at 1, 0 is used by kernel notifications
+    fn next_seq(&mut self) -> u32 {
+        self.seq = self.seq.wrapping_add(1);
+        if self.seq == 0 {
+            self.seq = 1;
+        }
+        self.seq
+    }
+
+    /// Set socket option, e.g. `SockOpt::ExtAck` to get
+    /// extended error reports from the kernel
+    pub fn setsockopt(&mut self, option: SockOpt, val: bool) -> Result<()> {
+        try!(self.sock.setsockopt(option, val));
+        Ok(())
+    }
+}
+
+impl AsRawFd for NetlinkConnection {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+#[cfg(feature = "mio")]
+impl Evented for NetlinkConnection {
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        self.sock.register(poll, token, interest, opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        self.sock.reregister(poll, token, interest, opts)
     }
 
-    pub fn send<'a,'b>(&'a mut self, msg: NetlinkPacket<'b>) -> NetlinkReader<&'a mut NetlinkConnection> {
-        self.sock.send(msg.packet()).unwrap();
-        NetlinkReader::new(self)
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        self.sock.deregister(poll)
     }
 }
 
@@ -1,5 +1,5 @@
 //! Address operations
-use packet::route::{IfAddrCacheInfoPacket,MutableIfInfoPacket,IfAddrPacket,MutableIfAddrPacket,RtAttrIterator,RtAttrPacket,MutableRtAttrPacket,RtAttrMtuPacket};
+use packet::route::{MutableIfInfoPacket,IfAddrPacket,MutableIfAddrPacket,RtAttrIterator,RtAttrPacket,MutableRtAttrPacket,RtAttrMtuPacket};
 use packet::route::link::Link;
 use packet::netlink::{MutableNetlinkPacket,NetlinkPacket,NetlinkErrorPacket};
 use packet::netlink::{NLM_F_ACK,NLM_F_REQUEST,NLM_F_DUMP,NLM_F_MATCH,NLM_F_EXCL,NLM_F_CREATE};
@@ -7,12 +7,19 @@ use packet::netlink::{NLMSG_NOOP,NLMSG_ERROR,NLMSG_DONE,NLMSG_OVERRUN};
 use packet::netlink::{NetlinkBufIterator,NetlinkReader,NetlinkRequestBuilder};
 use socket::{NetlinkSocket,NetlinkProtocol};
 use packet::netlink::NetlinkConnection;
+use ::error::Result;
+#[cfg(feature = "async")]
+use packet::async_netlink::AsyncNetlinkConnection;
+#[cfg(feature = "async")]
+use futures::{Future,Stream};
+#[cfg(feature = "async")]
+use ::error::Error;
 use pnet::packet::MutablePacket;
 use pnet::packet::Packet;
 use pnet::packet::PacketSize;
 use pnet::util::MacAddr;
 use libc;
-use std::io::{Read,Write,Cursor,self};
+use std::io::{Read,Write,Cursor};
 use byteorder::{LittleEndian, BigEndian, ReadBytesExt};
 use std::net::{Ipv4Addr,Ipv6Addr, IpAddr};
 
@@ -90,17 +97,26 @@ pub struct AddrsIterator<R: Read> {
 }
 
 impl<R: Read> Iterator for AddrsIterator<R> {
-    type Item = Addr;
+    type Item = Result<Addr>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.iter.next() {
-            Some(pkt) => {
+        self.next_ref().map(|res| res.map(|addr| addr.into_owned()))
+    }
+}
+
+impl<R: Read> AddrsIterator<R> {
+    /// Same as `next`, but the address is borrowed from the reader's
+    /// buffer instead of being copied
+    pub fn next_ref(&mut self) -> Option<Result<AddrView>> {
+        match self.iter.next_ref_skipping(|_| false) {
+            Some(Ok(pkt)) => {
                 let kind = pkt.get_kind();
                 if kind != RTM_NEWADDR {
                     return None;
                 }
-                return Some(Addr { packet: pkt });
+                return Some(Ok(AddrView { packet: pkt }));
             },
+            Some(Err(e)) => Some(Err(e)),
             None => None,
         }
     }
@@ -129,96 +145,150 @@ impl ToByteVec for IpAddr {
 
 /// Address operations trait
 pub trait Addresses where Self: Read + Write {
-    fn iter_addrs<'a>(&'a mut self, family: Option<u8>) -> io::Result<Box<Iterator<Item = Addr> + 'a>>;
-    fn get_link_addrs<'a,'b>(&'a mut self, family: Option<u8>, link: &'b Link) -> io::Result<Box<Iterator<Item = Addr> + 'a>>;
-    fn add_addr<'a,'b>(&'a mut self, link: &'b Link, addr: IpAddr, dst_addr: Option<IpAddr>, scope: Scope) -> io::Result<()>;
+    fn iter_addrs<'a>(&'a mut self, family: Option<u8>) -> Result<Box<Iterator<Item = Result<Addr>> + 'a>>;
+    fn get_link_addrs<'a,'b>(&'a mut self, family: Option<u8>, link: &'b Link) -> Result<Box<Iterator<Item = Result<Addr>> + 'a>>;
+    fn add_addr<'a,'b>(&'a mut self, link: &'b Link, addr: IpAddr, dst_addr: Option<IpAddr>, scope: Scope) -> Result<()>;
+    fn dump_addrs(&mut self, family: Option<u8>, retries: u32) -> Result<Vec<Addr>>;
 }
 
 impl Addresses for NetlinkConnection {
     /// Iterate over all addresses
-    fn iter_addrs<'a>(&'a mut self, family: Option<u8>) -> io::Result<Box<Iterator<Item = Addr> + 'a>> {
-        let mut buf = vec![0; MutableIfInfoPacket::minimum_packet_size()];
-        let req = NetlinkRequestBuilder::new(RTM_GETADDR, NLM_F_DUMP)
-            .append({
-                let mut ifinfo = MutableIfInfoPacket::new(&mut buf).unwrap();
-                ifinfo.set_family(family.unwrap_or(0));
-                ifinfo
-            }).build();
-        try!(self.write(req.packet()));
-        let reader = NetlinkReader::new(self);
+    fn iter_addrs<'a>(&'a mut self, family: Option<u8>) -> Result<Box<Iterator<Item = Result<Addr>> + 'a>> {
+        let reader = try!(self.send(dump_addrs_request(family)));
         let iter = AddrsIterator { iter: reader.into_iter() };
         Ok(Box::new(iter))
     }
 
+    /// Dump all addresses, restarting interrupted dump up to `retries` times
+    fn dump_addrs(&mut self, family: Option<u8>, retries: u32) -> Result<Vec<Addr>> {
+        let pkts = try!(self.dump(dump_addrs_request(family), retries));
+        Ok(pkts.into_iter()
+            .filter(|pkt| pkt.get_kind() == RTM_NEWADDR)
+            .map(|pkt| Addr { packet: pkt })
+            .collect())
+    }
+
     /// Iterate over `family` addresses for `link`
-    fn get_link_addrs<'a,'b>(&'a mut self, family: Option<u8>, link: &'b Link) -> io::Result<Box<Iterator<Item = Addr> + 'a>> {
+    fn get_link_addrs<'a,'b>(&'a mut self, family: Option<u8>, link: &'b Link) -> Result<Box<Iterator<Item = Result<Addr>> + 'a>> {
         let idx = link.get_index();
-        let mut buf = vec![0; MutableIfInfoPacket::minimum_packet_size()];
-        let req = NetlinkRequestBuilder::new(RTM_GETADDR, NLM_F_DUMP)
-            .append({
-                let mut ifinfo = MutableIfInfoPacket::new(&mut buf).unwrap();
-                ifinfo.set_family(family.unwrap_or(0));
-                ifinfo
-            }).build();
-        try!(self.write(req.packet()));
-        let reader = NetlinkReader::new(self);
+        let reader = try!(self.send(dump_addrs_request(family)));
         let iter = AddrsIterator { iter: reader.into_iter() };
-        Ok(Box::new(iter.filter(move |addr| addr.with_ifaddr(|ifa| ifa.get_index() == idx))))
+        Ok(Box::new(iter.filter(move |addr| match *addr {
+            Ok(ref addr) => addr.with_ifaddr(|ifa| ifa.get_index() == idx),
+            Err(_) => true,
+        })))
     }
 
     /// Add address `addr` to `link` with scope `scope`
     /// If `dst_addr` is not None, the IFA_ADDRESS will be set to `dst_addr`.
     /// If `dst_addr` is None, the IFA_ADDRESS will be set to `addr`.
-    fn add_addr<'a,'b>(&'a mut self, link: &'b Link, addr: IpAddr, dst_addr: Option<IpAddr>, scope: Scope) -> io::Result<()> {
-        let link_index = link.get_index();
-        let family = match addr {
-            IpAddr::V4(_) => 2,
-            IpAddr::V6(_) => 10,
-        };
-        let prefix_len = 32; /* XXX: FIXME */
-        let ip_addr_len = addr.bytes().len();
-        let mut buf = vec![0; MutableIfAddrPacket::minimum_packet_size()];
-        let mut rta_buf = vec![0; MutableRtAttrPacket::minimum_packet_size() + ip_addr_len];
-        let mut rta_buf1 = vec![0; MutableRtAttrPacket::minimum_packet_size() + ip_addr_len];
-        let req = IfAddrRequestBuilder::new().with_ifa(|mut ifaddr| {
-                ifaddr.set_index(link_index);
-                ifaddr.set_family(family);
-                ifaddr.set_scope(scope);
-                ifaddr.set_prefix_len(prefix_len);
-        }).append({
-            {
-                let mut pkt = MutableRtAttrPacket::new(&mut rta_buf).unwrap();
-                pkt.set_rta_len(4 + ip_addr_len as u16);
-                pkt.set_rta_type(IFA_ADDRESS);
-                let mut pl = pkt.payload_mut();
-                pl.copy_from_slice(&dst_addr.as_ref().unwrap_or_else(|| &addr).bytes());
-            }
-            RtAttrPacket::new(&mut rta_buf).unwrap()
-        }).append({
-            {
-                let mut pkt = MutableRtAttrPacket::new(&mut rta_buf1).unwrap();
-                pkt.set_rta_len(4 + ip_addr_len as u16);
-                pkt.set_rta_type(IFA_LOCAL);
-                let mut pl = pkt.payload_mut();
-                pl.copy_from_slice(&addr.bytes());
-            }
-            RtAttrPacket::new(&mut rta_buf1).unwrap()
-        }).build();
-        let req = NetlinkRequestBuilder::new(RTM_NEWADDR, NLM_F_CREATE | NLM_F_EXCL | NLM_F_ACK)
-            .append(req).build();
-        self.write(req.packet());
-        let reader = NetlinkReader::new(self);
+    fn add_addr<'a,'b>(&'a mut self, link: &'b Link, addr: IpAddr, dst_addr: Option<IpAddr>, scope: Scope) -> Result<()> {
+        let reader = try!(self.send(add_addr_request(link.get_index(), addr, dst_addr, scope)));
         reader.read_to_end()
     }
 }
 
-/// Address
+/// Asynchronous address operations trait
+#[cfg(feature = "async")]
+pub trait AsyncAddresses {
+    /// iterate over `family` addresses, all families if None
+    fn iter_addrs(&self, family: Option<u8>) -> Box<Stream<Item = Addr, Error = Error>>;
+    /// iterate over `family` addresses for `link`
+    fn get_link_addrs(&self, family: Option<u8>, link: &Link) -> Box<Stream<Item = Addr, Error = Error>>;
+    /// add address `addr` to `link`, see `Addresses::add_addr`
+    fn add_addr(&self, link: &Link, addr: IpAddr, dst_addr: Option<IpAddr>, scope: Scope) -> Box<Future<Item = (), Error = Error>>;
+}
+
+#[cfg(feature = "async")]
+impl AsyncAddresses for AsyncNetlinkConnection {
+    fn iter_addrs(&self, family: Option<u8>) -> Box<Stream<Item = Addr, Error = Error>> {
+        Box::new(self.request(dump_addrs_request(family))
+            .take_while(|pkt| Ok(pkt.get_kind() == RTM_NEWADDR))
+            .map(|pkt| Addr { packet: pkt }))
+    }
+
+    fn get_link_addrs(&self, family: Option<u8>, link: &Link) -> Box<Stream<Item = Addr, Error = Error>> {
+        let idx = link.get_index();
+        Box::new(self.iter_addrs(family).filter(move |addr| addr.get_link_index() == idx))
+    }
+
+    fn add_addr(&self, link: &Link, addr: IpAddr, dst_addr: Option<IpAddr>, scope: Scope) -> Box<Future<Item = (), Error = Error>> {
+        self.request_ack(add_addr_request(link.get_index(), addr, dst_addr, scope))
+    }
+}
+
+fn add_addr_request(link_index: u32, addr: IpAddr, dst_addr: Option<IpAddr>, scope: Scope) -> NetlinkPacket<'static> {
+    let family = match addr {
+        IpAddr::V4(_) => 2,
+        IpAddr::V6(_) => 10,
+    };
+    let prefix_len = 32; /* XXX: FIXME */
+    let ip_addr_len = addr.bytes().len();
+    let mut buf = vec![0; MutableIfAddrPacket::minimum_packet_size()];
+    let mut rta_buf = vec![0; MutableRtAttrPacket::minimum_packet_size() + ip_addr_len];
+    let mut rta_buf1 = vec![0; MutableRtAttrPacket::minimum_packet_size() + ip_addr_len];
+    let req = IfAddrRequestBuilder::new().with_ifa(|mut ifaddr| {
+            ifaddr.set_index(link_index);
+            ifaddr.set_family(family);
+            ifaddr.set_scope(scope);
+            ifaddr.set_prefix_len(prefix_len);
+    }).append({
+        {
+            let mut pkt = MutableRtAttrPacket::new(&mut rta_buf).unwrap();
+            pkt.set_rta_len(4 + ip_addr_len as u16);
+            pkt.set_rta_type(IFA_ADDRESS);
+            let mut pl = pkt.payload_mut();
+            pl.copy_from_slice(&dst_addr.as_ref().unwrap_or_else(|| &addr).bytes());
+        }
+        RtAttrPacket::new(&mut rta_buf).unwrap()
+    }).append({
+        {
+            let mut pkt = MutableRtAttrPacket::new(&mut rta_buf1).unwrap();
+            pkt.set_rta_len(4 + ip_addr_len as u16);
+            pkt.set_rta_type(IFA_LOCAL);
+            let mut pl = pkt.payload_mut();
+            pl.copy_from_slice(&addr.bytes());
+        }
+        RtAttrPacket::new(&mut rta_buf1).unwrap()
+    }).build();
+    NetlinkRequestBuilder::new(RTM_NEWADDR, NLM_F_CREATE | NLM_F_EXCL | NLM_F_ACK)
+        .append(req).build()
+}
+
+fn dump_addrs_request(family: Option<u8>) -> NetlinkPacket<'static> {
+    let mut buf = vec![0; MutableIfInfoPacket::minimum_packet_size()];
+    NetlinkRequestBuilder::new(RTM_GETADDR, NLM_F_DUMP)
+        .append({
+            let mut ifinfo = MutableIfInfoPacket::new(&mut buf).unwrap();
+            ifinfo.set_family(family.unwrap_or(0));
+            ifinfo
+        }).build()
+}
+
+/// Address, either owned (`Addr`) or borrowed from
+/// a reader's buffer (`AddrsIterator::next_ref`)
 #[derive(Debug)]
-pub struct Addr {
-    packet: NetlinkPacket<'static>,
+pub struct AddrView<'a> {
+    packet: NetlinkPacket<'a>,
 }
 
-impl Addr {
+/// Address message owning its buffer
+pub type Addr = AddrView<'static>;
+
+impl<'a> AddrView<'a> {
+    /// Wraps RTM_NEWADDR or RTM_DELADDR message, e.g. a notification
+    pub fn from_packet(packet: NetlinkPacket<'a>) -> Option<AddrView<'a>> {
+        match packet.get_kind() {
+            RTM_NEWADDR | RTM_DELADDR if IfAddrPacket::new(packet.payload()).is_some() => Some(AddrView { packet: packet }),
+            _ => None,
+        }
+    }
+
+    /// Copies the message out of the buffer it is borrowed from
+    pub fn into_owned(self) -> Addr {
+        AddrView { packet: NetlinkPacket::owned(self.packet.packet().to_owned()).unwrap() }
+    }
+
     /// get address family
     pub fn get_family(&self) -> u8 {
         self.with_ifaddr(|ifa| ifa.get_family())
@@ -327,8 +397,10 @@ impl Addr {
         }
     }
 
+    #[cfg(test)]
     fn dump_addr(msg: NetlinkPacket) {
         use std::ffi::CStr;
+        use packet::route::IfAddrCacheInfoPacket;
         if msg.get_kind() != RTM_NEWADDR {
             return;
         }
@@ -381,16 +453,9 @@ impl Addr {
         }
     }
 
-    pub fn iter_addrs(conn: &mut NetlinkConnection) -> AddrsIterator<&mut NetlinkConnection> {
-        let mut buf = vec![0; MutableIfInfoPacket::minimum_packet_size()];
-        let req = NetlinkRequestBuilder::new(RTM_GETADDR, NLM_F_DUMP)
-        .append({
-            let mut ifinfo = MutableIfInfoPacket::new(&mut buf).unwrap();
-            ifinfo.set_family(0 /* AF_UNSPEC */);
-            ifinfo
-        }).build();
-        let mut reply = conn.send(req);
-        AddrsIterator { iter: reply.into_iter() }
+    pub fn iter_addrs(conn: &mut NetlinkConnection) -> Result<AddrsIterator<&mut NetlinkConnection>> {
+        let mut reply = try!(conn.send(dump_addrs_request(None)));
+        Ok(AddrsIterator { iter: reply.into_iter() })
     }
 }
 
@@ -434,10 +499,25 @@ fn dump_addrs() {
     use packet::netlink::NetlinkConnection;
     use packet::route::addr::Addresses;
 
-    let mut conn = NetlinkConnection::new();
+    let mut conn = NetlinkConnection::new().unwrap();
     for addr in conn.iter_addrs(None).unwrap() {
-        Addr::dump_addr(addr.packet);
+        Addr::dump_addr(addr.unwrap().packet);
+    }
+}
+
+#[test]
+fn iter_addrs_ref() {
+    use packet::netlink::NetlinkConnection;
+
+    let mut conn = NetlinkConnection::new().unwrap();
+    let mut addrs = Addr::iter_addrs(&mut conn).unwrap();
+    let mut found = false;
+    while let Some(addr) = addrs.next_ref() {
+        if addr.unwrap().get_ip() == Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))) {
+            found = true;
+        }
     }
+    assert!(found);
 }
 
 /*
@@ -8,27 +8,37 @@
 //! use pnetlink::packet::route::link::{Links,Link};
 //! use pnetlink::packet::route::addr::{Addresses,Addr};
 //!
-//! let mut conn = NetlinkConnection::new();
-//! let links = conn.iter_links().unwrap().collect::<Vec<_>>();
+//! let mut conn = NetlinkConnection::new().unwrap();
+//! let links = conn.iter_links().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
 //! for link in links {
 //!    ...
 //! }
 //! ```
 
 
-use packet::route::{IfInfoPacket,MutableIfInfoPacket,RtAttrIterator,RtAttrPacket,MutableRtAttrPacket,RtAttrMtuPacket};
+use packet::route::{IfInfoPacket,MutableIfInfoPacket,RtAttrIterator,RtAttrPacket,MutableRtAttrPacket,RtAttrMtuPacket,MutableRtAttrMtuPacket};
+use packet::route::{append_rta,append_rta_str,append_rta_u32,read_string,read_u32};
 use packet::netlink::{MutableNetlinkPacket,NetlinkPacket,NetlinkErrorPacket};
 use packet::netlink::{NLM_F_ACK,NLM_F_REQUEST,NLM_F_DUMP,NLM_F_MATCH,NLM_F_EXCL,NLM_F_CREATE};
 use packet::netlink::{NLMSG_NOOP,NLMSG_ERROR,NLMSG_DONE,NLMSG_OVERRUN};
 use packet::netlink::{NetlinkBufIterator,NetlinkReader,NetlinkRequestBuilder};
 use ::socket::{NetlinkSocket,NetlinkProtocol};
 use packet::netlink::NetlinkConnection;
+use ::error::Result;
+#[cfg(feature = "async")]
+use packet::async_netlink::AsyncNetlinkConnection;
+#[cfg(feature = "async")]
+use futures::{Future,Stream};
+#[cfg(feature = "async")]
+use ::error::Error;
 use pnet::packet::MutablePacket;
 use pnet::packet::Packet;
 use pnet::packet::PacketSize;
 use pnet::util::MacAddr;
+use byteorder::{ByteOrder,NativeEndian};
 use libc;
-use std::io::{Read,Write,self};
+use std::io::{Read,Write};
+use std::os::unix::io::RawFd;
 
 /* rt message types */
 pub const RTM_NEWLINK: u16 = 16;
@@ -86,6 +96,27 @@ pub const IFLA_INFO_KIND: u16 = 1;
 pub const IFLA_INFO_DATA: u16 = 2;
 pub const IFLA_INFO_XSTATS: u16 = 3;
 
+pub const VETH_INFO_UNSPEC: u16 = 0;
+pub const VETH_INFO_PEER: u16 = 1;
+
+/// Network namespace a link is moved to
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Netns {
+    /// Namespace of the process with this pid
+    Pid(u32),
+    /// Namespace referred by an open file descriptor, e.g. of `/var/run/netns/<name>`
+    Fd(RawFd),
+}
+
+impl Netns {
+    fn append_to(&self, buf: &mut Vec<u8>) {
+        match *self {
+            Netns::Pid(pid) => append_rta_u32(buf, IFLA_NET_NS_PID, pid),
+            Netns::Fd(fd) => append_rta_u32(buf, IFLA_NET_NS_FD, fd as u32),
+        }
+    }
+}
+
 /// Interface type
 /// NB: Only Generic, Ether and Loopback are currently defined
 #[derive(Debug,Copy,Clone)]
@@ -180,33 +211,65 @@ pub enum OperState {
     Up = 6,
 }
 
-/// Link is a virtual of physical interface
-pub struct Link {
-    packet: NetlinkPacket<'static>
+/// Link is a virtual of physical interface. The message is either
+/// owned (`Link`) or borrowed from a reader's buffer (`LinksIterator::next_ref`)
+pub struct LinkView<'a> {
+    packet: NetlinkPacket<'a>
 }
 
+/// Link message owning its buffer
+pub type Link = LinkView<'static>;
+
 pub struct LinksIterator<R: Read> {
     iter: NetlinkBufIterator<R>,
+    filter: LinkFilter,
 }
 
 impl<R: Read> Iterator for LinksIterator<R> {
-    type Item = Link;
+    type Item = Result<Link>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.iter.next() {
-            Some(pkt) => {
+        self.next_ref().map(|res| res.map(|link| link.into_owned()))
+    }
+}
+
+impl<R: Read> LinksIterator<R> {
+    /// Same as `next`, but the link is borrowed from the reader's
+    /// buffer instead of being copied
+    pub fn next_ref(&mut self) -> Option<Result<LinkView>> {
+        let filter = &self.filter;
+        let next = self.iter.next_ref_skipping(|pkt| {
+            pkt.get_kind() == RTM_NEWLINK &&
+                !filter.matches(&LinkView { packet: NetlinkPacket::new(pkt.packet()).unwrap() })
+        });
+        match next {
+            Some(Ok(pkt)) => {
                 let kind = pkt.get_kind();
                 if kind != RTM_NEWLINK {
                     return None;
                 }
-                return Some(Link { packet: pkt });
+                return Some(Ok(LinkView { packet: pkt }));
             },
+            Some(Err(e)) => Some(Err(e)),
             None => None,
         }
     }
+
+    /// Returns the last link, ENODEV reply means there is no such link
+    fn last_link(self) -> Result<Option<Link>> {
+        let mut last = None;
+        for link in self {
+            match link {
+                Ok(link) => last = Some(link),
+                Err(ref e) if e.raw_os_error() == Some(libc::ENODEV) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(last)
+    }
 }
 
-impl ::std::fmt::Debug for Link {
+impl<'a> ::std::fmt::Debug for LinkView<'a> {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
         write!(f, "{}: {:?}", self.get_index(), self.get_name())
     }
@@ -215,167 +278,550 @@ impl ::std::fmt::Debug for Link {
 /// Links operation trait
 pub trait Links where Self: Read + Write {
     /// iterate over links
-    fn iter_links(&mut self) -> io::Result<Box<LinksIterator<&mut Self>>>;
+    fn iter_links(&mut self) -> Result<Box<LinksIterator<&mut Self>>>;
+    /// dump all links, restarting interrupted dump up to `retries` times
+    fn dump_links(&mut self, retries: u32) -> Result<Vec<Link>>;
+    /// iterate over links matching `filter`
+    fn iter_links_filtered(&mut self, filter: &LinkFilter) -> Result<Box<LinksIterator<&mut Self>>>;
+    /// dump links matching `filter`, restarting interrupted dump up to `retries` times
+    fn dump_links_filtered(&mut self, filter: &LinkFilter, retries: u32) -> Result<Vec<Link>>;
     /// returns link by its index
-    fn get_link_by_index(&mut self, index: u32) -> io::Result<Option<Link>>;
+    fn get_link_by_index(&mut self, index: u32) -> Result<Option<Link>>;
     /// returns link by its name
-    fn get_link_by_name(&mut self, name: &str) -> io::Result<Option<Link>>;
+    fn get_link_by_name(&mut self, name: &str) -> Result<Option<Link>>;
     /// delete link
-    fn delete_link(&mut self, link: Link) -> io::Result<()>;
+    fn delete_link(&mut self, link: Link) -> Result<()>;
     /// create dummy link
-    fn new_dummy_link(&mut self, name: &str) -> io::Result<()>;
+    fn new_dummy_link(&mut self, name: &str) -> Result<()>;
+    /// create veth pair, the peer is created in `peer_netns` if given
+    fn create_veth(&mut self, name: &str, peer_name: &str, peer_netns: Option<Netns>) -> Result<()>;
+    /// move link to another network namespace
+    fn link_set_netns(&mut self, index: u32, netns: Netns) -> Result<()>;
     /// set link down
-    fn link_set_down(&mut self, index: u32) -> io::Result<()>;
+    fn link_set_down(&mut self, index: u32) -> Result<()>;
     /// set link up
-    fn link_set_up(&mut self, index: u32) -> io::Result<()>;
+    fn link_set_up(&mut self, index: u32) -> Result<()>;
+    /// apply all settings of `change` to the link at once
+    fn link_change(&mut self, change: LinkChange) -> Result<()>;
 }
 
 impl Links for NetlinkConnection {
-    fn iter_links(&mut self) -> io::Result<Box<LinksIterator<&mut Self>>> {
-        let req = NetlinkRequestBuilder::new(RTM_GETLINK, NLM_F_DUMP)
-            .append(
-                IfInfoPacketBuilder::new()
-                    .build()
-            ).build();
-        try!(self.write(req.packet()));
-        let reader = NetlinkReader::new(self);
-        Ok(Box::new(LinksIterator { iter: reader.into_iter() }))
-    }
-
-    fn get_link_by_index(&mut self, index: u32) -> io::Result<Option<Link>> {
-        let mut req = {
-            let mut buf = vec![0; MutableIfInfoPacket::minimum_packet_size()];
-            NetlinkRequestBuilder::new(RTM_GETLINK, NLM_F_ACK)
-            .append(
-                IfInfoPacketBuilder::new()
-                    .set_index(index)
-                    .build()
-            ).build()
-        };
-        try!(self.write(req.packet()));
-        let reader = NetlinkReader::new(self);
-        let li = LinksIterator { iter: reader.into_iter() };
-        Ok(li.last())
-    }
-
-    fn get_link_by_name(&mut self, name: &str) -> io::Result<Option<Link>> {
-        let req = {
-            let name_len = name.as_bytes().len();
-            NetlinkRequestBuilder::new(RTM_GETLINK, NLM_F_ACK).append({
-                let mut buf = vec![0; RtAttrPacket::minimum_packet_size() + name_len + 1];
-                IfInfoPacketBuilder::new().append({
-                    {
-                        let mut ifname_rta = MutableRtAttrPacket::new(&mut buf).unwrap();
-                        ifname_rta.set_rta_type(IFLA_IFNAME);
-                        ifname_rta.set_rta_len((RtAttrPacket::minimum_packet_size() + name_len + 1) as u16);
-                        let mut payload = ifname_rta.payload_mut();
-                        payload[0..name_len].copy_from_slice(name.as_bytes());
-                    }
-                    RtAttrPacket::new(&buf[..]).unwrap()
-                }).build()
-            }).build()
-        };
-        try!(self.write(req.packet()));
-        let reader = NetlinkReader::new(self);
-        let li = LinksIterator { iter: reader.into_iter() };
-        Ok(li.last())
-    }
-
-    fn new_dummy_link(&mut self, name: &str) -> io::Result<()> {
-        let mut ifi = {
-            let mut buf = vec![0; 32];
-            let name_len = name.as_bytes().len();
-            let mut buf_name = vec![0; RtAttrPacket::minimum_packet_size() + name_len + 1];
-            IfInfoPacketBuilder::new().
-                append({
-                    {
-                        let mut ifname_rta = MutableRtAttrPacket::new(&mut buf_name).unwrap();
-                        ifname_rta.set_rta_type(IFLA_IFNAME);
-                        ifname_rta.set_rta_len(4 + name_len as u16 + 1);
-                        let mut payload = ifname_rta.payload_mut();
-                        payload[0..name_len].copy_from_slice(name.as_bytes());
-                    }
-                    RtAttrPacket::new(&buf_name).unwrap()
-                }).
-                append({
-                    {
-                        let mut link_info_rta = MutableRtAttrPacket::new(&mut buf).unwrap();
-                        link_info_rta.set_rta_type(IFLA_LINKINFO);
-                        link_info_rta.set_rta_len(6 + 4 + 4);
-                        let mut info_kind_rta = MutableRtAttrPacket::new(link_info_rta.payload_mut()).unwrap();
-                        info_kind_rta.set_rta_type(IFLA_INFO_KIND);
-                        info_kind_rta.set_rta_len(6 + 4);
-                        let mut payload = info_kind_rta.payload_mut();
-                        payload[0..6].copy_from_slice(b"dummy\0");
-                    }
-                    RtAttrPacket::new(&buf).unwrap()
-            }).build()
-        };
-        let req = NetlinkRequestBuilder::new(RTM_NEWLINK, NLM_F_CREATE | NLM_F_EXCL | NLM_F_ACK)
-            .append(ifi).build();
-        try!(self.write(req.packet()));
-        let reader = NetlinkReader::new(self);
+    fn iter_links(&mut self) -> Result<Box<LinksIterator<&mut Self>>> {
+        let reader = try!(self.send(dump_links_request()));
+        Ok(Box::new(LinksIterator { iter: reader.into_iter(), filter: LinkFilter::new() }))
+    }
+
+    fn dump_links(&mut self, retries: u32) -> Result<Vec<Link>> {
+        let pkts = try!(self.dump(dump_links_request(), retries));
+        Ok(pkts.into_iter()
+            .filter(|pkt| pkt.get_kind() == RTM_NEWLINK)
+            .map(|pkt| Link { packet: pkt })
+            .collect())
+    }
+
+    fn iter_links_filtered(&mut self, filter: &LinkFilter) -> Result<Box<LinksIterator<&mut Self>>> {
+        let reader = try!(self.send(filter.build_request()));
+        Ok(Box::new(LinksIterator { iter: reader.into_iter(), filter: filter.clone() }))
+    }
+
+    fn dump_links_filtered(&mut self, filter: &LinkFilter, retries: u32) -> Result<Vec<Link>> {
+        let pkts = try!(self.dump(filter.build_request(), retries));
+        Ok(pkts.into_iter()
+            .filter(|pkt| pkt.get_kind() == RTM_NEWLINK)
+            .map(|pkt| Link { packet: pkt })
+            .filter(|link| filter.matches(link))
+            .collect())
+    }
+
+    fn get_link_by_index(&mut self, index: u32) -> Result<Option<Link>> {
+        let reader = try!(self.send(get_link_by_index_request(index)));
+        let li = LinksIterator { iter: reader.into_iter(), filter: LinkFilter::new() };
+        li.last_link()
+    }
+
+    fn get_link_by_name(&mut self, name: &str) -> Result<Option<Link>> {
+        let reader = try!(self.send(get_link_by_name_request(name)));
+        let li = LinksIterator { iter: reader.into_iter(), filter: LinkFilter::new() };
+        li.last_link()
+    }
+
+    fn new_dummy_link(&mut self, name: &str) -> Result<()> {
+        let reader = try!(self.send(new_dummy_link_request(name)));
         reader.read_to_end()
     }
 
-    fn delete_link(&mut self, link: Link) -> io::Result<()> {
-        let index = link.get_index();
-        let mut req = {
-            let mut buf = vec![0; MutableIfInfoPacket::minimum_packet_size()];
-            NetlinkRequestBuilder::new(RTM_DELLINK, NLM_F_ACK)
-            .append({
-                let mut ifinfo = MutableIfInfoPacket::new(&mut buf).unwrap();
-                ifinfo.set_family(0 /* AF_UNSPEC */);
-                ifinfo.set_index(index);
-                ifinfo
-            }).build()
-        };
-        try!(self.write(req.packet()));
-        let reader = NetlinkReader::new(self);
+    fn create_veth(&mut self, name: &str, peer_name: &str, peer_netns: Option<Netns>) -> Result<()> {
+        let reader = try!(self.send(create_veth_request(name, peer_name, peer_netns)));
         reader.read_to_end()
     }
 
-    fn link_set_down(&mut self, index: u32) -> io::Result<()> {
-        let mut req = {
-            let mut buf = vec![0; MutableIfInfoPacket::minimum_packet_size()];
-            NetlinkRequestBuilder::new(RTM_NEWLINK, NLM_F_ACK)
-                .append({
-                    let mut ifinfo = MutableIfInfoPacket::new(&mut buf).unwrap();
-                    ifinfo.set_family(0 /* AF_UNSPEC */);
-                    ifinfo.set_index(index);
-                    ifinfo.set_change(UP.bits);
-                    ifinfo.set_flags(IfFlags::new(0) & !UP);
-                    ifinfo
-                }).build()
-        };
+    fn link_set_netns(&mut self, index: u32, netns: Netns) -> Result<()> {
+        self.link_change(LinkChange::new(index).set_netns(netns))
+    }
 
-       try!(self.write(req.packet()));
-       let reader = NetlinkReader::new(self);
-       reader.read_to_end()
-    }
-    
-    fn link_set_up(&mut self, index: u32) -> io::Result<()> {
-        let mut req = {
-            let mut buf = vec![0; MutableIfInfoPacket::minimum_packet_size()];
-            NetlinkRequestBuilder::new(RTM_NEWLINK, NLM_F_ACK)
-                .append({
-                    let mut ifinfo = MutableIfInfoPacket::new(&mut buf).unwrap();
-                    ifinfo.set_family(0 /* AF_UNSPEC */);
-                    ifinfo.set_index(index);
-                    ifinfo.set_change(UP.bits);
-                    ifinfo.set_flags(UP);
-                    ifinfo
-                }).build()
-        };
+    fn delete_link(&mut self, link: Link) -> Result<()> {
+        let reader = try!(self.send(delete_link_request(link.get_index())));
+        reader.read_to_end()
+    }
+
+    fn link_set_down(&mut self, index: u32) -> Result<()> {
+        self.link_change(LinkChange::new(index).set_up(false))
+    }
+
+    fn link_set_up(&mut self, index: u32) -> Result<()> {
+        self.link_change(LinkChange::new(index).set_up(true))
+    }
+
+    fn link_change(&mut self, change: LinkChange) -> Result<()> {
+        let reader = try!(self.send(change.build_request()));
+        reader.read_to_end()
+    }
+}
+
+/// Asynchronous links operation trait
+#[cfg(feature = "async")]
+pub trait AsyncLinks {
+    /// iterate over links
+    fn iter_links(&self) -> Box<Stream<Item = Link, Error = Error>>;
+    /// returns link by its index
+    fn get_link_by_index(&self, index: u32) -> Box<Future<Item = Option<Link>, Error = Error>>;
+    /// returns link by its name
+    fn get_link_by_name(&self, name: &str) -> Box<Future<Item = Option<Link>, Error = Error>>;
+    /// delete link
+    fn delete_link(&self, link: Link) -> Box<Future<Item = (), Error = Error>>;
+    /// create dummy link
+    fn new_dummy_link(&self, name: &str) -> Box<Future<Item = (), Error = Error>>;
+    /// create veth pair, the peer is created in `peer_netns` if given
+    fn create_veth(&self, name: &str, peer_name: &str, peer_netns: Option<Netns>) -> Box<Future<Item = (), Error = Error>>;
+    /// move link to another network namespace
+    fn link_set_netns(&self, index: u32, netns: Netns) -> Box<Future<Item = (), Error = Error>>;
+    /// set link down
+    fn link_set_down(&self, index: u32) -> Box<Future<Item = (), Error = Error>>;
+    /// set link up
+    fn link_set_up(&self, index: u32) -> Box<Future<Item = (), Error = Error>>;
+    /// apply all settings of `change` to the link at once
+    fn link_change(&self, change: LinkChange) -> Box<Future<Item = (), Error = Error>>;
+}
+
+#[cfg(feature = "async")]
+impl AsyncLinks for AsyncNetlinkConnection {
+    fn iter_links(&self) -> Box<Stream<Item = Link, Error = Error>> {
+        links_stream(self.request(dump_links_request()))
+    }
+
+    fn get_link_by_index(&self, index: u32) -> Box<Future<Item = Option<Link>, Error = Error>> {
+        last_link(links_stream(self.request(get_link_by_index_request(index))))
+    }
+
+    fn get_link_by_name(&self, name: &str) -> Box<Future<Item = Option<Link>, Error = Error>> {
+        last_link(links_stream(self.request(get_link_by_name_request(name))))
+    }
+
+    fn delete_link(&self, link: Link) -> Box<Future<Item = (), Error = Error>> {
+        self.request_ack(delete_link_request(link.get_index()))
+    }
+
+    fn new_dummy_link(&self, name: &str) -> Box<Future<Item = (), Error = Error>> {
+        self.request_ack(new_dummy_link_request(name))
+    }
+
+    fn create_veth(&self, name: &str, peer_name: &str, peer_netns: Option<Netns>) -> Box<Future<Item = (), Error = Error>> {
+        self.request_ack(create_veth_request(name, peer_name, peer_netns))
+    }
+
+    fn link_set_netns(&self, index: u32, netns: Netns) -> Box<Future<Item = (), Error = Error>> {
+        self.link_change(LinkChange::new(index).set_netns(netns))
+    }
+
+    fn link_set_down(&self, index: u32) -> Box<Future<Item = (), Error = Error>> {
+        self.link_change(LinkChange::new(index).set_up(false))
+    }
+
+    fn link_set_up(&self, index: u32) -> Box<Future<Item = (), Error = Error>> {
+        self.link_change(LinkChange::new(index).set_up(true))
+    }
+
+    fn link_change(&self, change: LinkChange) -> Box<Future<Item = (), Error = Error>> {
+        self.request_ack(change.build_request())
+    }
+}
+
+/// Links in `replies`, up to the first message of another kind
+#[cfg(feature = "async")]
+fn links_stream<S>(replies: S) -> Box<Stream<Item = Link, Error = Error>>
+    where S: Stream<Item = NetlinkPacket<'static>, Error = Error> + 'static {
+    Box::new(replies
+        .take_while(|pkt| Ok(pkt.get_kind() == RTM_NEWLINK))
+        .map(|pkt| Link { packet: pkt }))
+}
+
+/// Resolves to the last link in `links`, ENODEV reply means there is no such link
+#[cfg(feature = "async")]
+fn last_link<S>(links: S) -> Box<Future<Item = Option<Link>, Error = Error>>
+    where S: Stream<Item = Link, Error = Error> + 'static {
+    Box::new(links
+        .fold(None, |_, link| Ok::<_, Error>(Some(link)))
+        .or_else(|e| match e.raw_os_error() {
+            Some(libc::ENODEV) => Ok(None),
+            _ => Err(e),
+        }))
+}
+
+fn dump_links_request() -> NetlinkPacket<'static> {
+    NetlinkRequestBuilder::new(RTM_GETLINK, NLM_F_DUMP)
+        .append(
+            IfInfoPacketBuilder::new()
+                .build()
+        ).build()
+}
+
+/// Link dump filter, applied by the kernel and again in userspace
+/// as older kernels ignore it
+#[derive(Debug,Clone,Default)]
+pub struct LinkFilter {
+    master: Option<u32>,
+    kind: Option<String>,
+}
+
+impl LinkFilter {
+    /// Creates filter matching all links
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Match links enslaved to `master`, e.g. bridge ports
+    pub fn set_master(mut self, master: u32) -> Self {
+        self.master = Some(master);
+        self
+    }
+
+    /// Match links of `kind`, e.g. "dummy" or "veth"
+    pub fn set_kind(mut self, kind: &str) -> Self {
+        self.kind = Some(kind.to_owned());
+        self
+    }
+
+    /// Returns whether `link` passes the filter
+    pub fn matches(&self, link: &LinkView) -> bool {
+        self.master.map_or(true, |master| link.get_master() == Some(master)) &&
+            self.kind.as_ref().map_or(true, |kind| link.get_kind().as_ref() == Some(kind))
+    }
+
+    fn build_request(&self) -> NetlinkPacket<'static> {
+        let mut data = vec![0; MutableIfInfoPacket::minimum_packet_size()];
+        if let Some(master) = self.master {
+            append_rta_u32(&mut data, IFLA_MASTER, master);
+        }
+        if let Some(ref kind) = self.kind {
+            let mut linkinfo = vec![];
+            append_rta_str(&mut linkinfo, IFLA_INFO_KIND, kind);
+            append_rta(&mut data, IFLA_LINKINFO, &linkinfo);
+        }
+        NetlinkRequestBuilder::new(RTM_GETLINK, NLM_F_DUMP)
+            .append(IfInfoPacket::owned(data).unwrap())
+            .build()
+    }
+}
+
+fn get_link_by_index_request(index: u32) -> NetlinkPacket<'static> {
+    NetlinkRequestBuilder::new(RTM_GETLINK, NLM_F_ACK)
+        .append(
+            IfInfoPacketBuilder::new()
+                .set_index(index)
+                .build()
+        ).build()
+}
+
+fn get_link_by_name_request(name: &str) -> NetlinkPacket<'static> {
+    let name_len = name.as_bytes().len();
+    NetlinkRequestBuilder::new(RTM_GETLINK, NLM_F_ACK).append({
+        let mut buf = vec![0; RtAttrPacket::minimum_packet_size() + name_len + 1];
+        IfInfoPacketBuilder::new().append({
+            {
+                let mut ifname_rta = MutableRtAttrPacket::new(&mut buf).unwrap();
+                ifname_rta.set_rta_type(IFLA_IFNAME);
+                ifname_rta.set_rta_len((RtAttrPacket::minimum_packet_size() + name_len + 1) as u16);
+                let mut payload = ifname_rta.payload_mut();
+                payload[0..name_len].copy_from_slice(name.as_bytes());
+            }
+            RtAttrPacket::new(&buf[..]).unwrap()
+        }).build()
+    }).build()
+}
+
+/// Builds RTM_NEWLINK request creating link `name` of `kind`
+/// with optional IFLA_INFO_DATA payload
+fn new_link_request(name: &str, kind: &str, info_data: Option<&[u8]>) -> NetlinkPacket<'static> {
+    let mut data = IfInfoPacketBuilder::new().build().packet().to_owned();
+    append_rta_str(&mut data, IFLA_IFNAME, name);
+    let mut linkinfo = vec![];
+    append_rta_str(&mut linkinfo, IFLA_INFO_KIND, kind);
+    if let Some(info_data) = info_data {
+        append_rta(&mut linkinfo, IFLA_INFO_DATA, info_data);
+    }
+    append_rta(&mut data, IFLA_LINKINFO, &linkinfo);
+    NetlinkRequestBuilder::new(RTM_NEWLINK, NLM_F_CREATE | NLM_F_EXCL | NLM_F_ACK)
+        .append(IfInfoPacket::owned(data).unwrap()).build()
+}
+
+fn new_dummy_link_request(name: &str) -> NetlinkPacket<'static> {
+    new_link_request(name, "dummy", None)
+}
+
+fn create_veth_request(name: &str, peer_name: &str, peer_netns: Option<Netns>) -> NetlinkPacket<'static> {
+    // VETH_INFO_PEER carries ifinfomsg of the peer followed by its attributes
+    let mut peer = IfInfoPacketBuilder::new().build().packet().to_owned();
+    append_rta_str(&mut peer, IFLA_IFNAME, peer_name);
+    if let Some(netns) = peer_netns {
+        netns.append_to(&mut peer);
+    }
+    let mut info_data = vec![];
+    append_rta(&mut info_data, VETH_INFO_PEER, &peer);
+    new_link_request(name, "veth", Some(&info_data))
+}
+
+fn delete_link_request(index: u32) -> NetlinkPacket<'static> {
+    let mut buf = vec![0; MutableIfInfoPacket::minimum_packet_size()];
+    NetlinkRequestBuilder::new(RTM_DELLINK, NLM_F_ACK)
+    .append({
+        let mut ifinfo = MutableIfInfoPacket::new(&mut buf).unwrap();
+        ifinfo.set_family(0 /* AF_UNSPEC */);
+        ifinfo.set_index(index);
+        ifinfo
+    }).build()
+}
+
+/// Settings of an existing link, applied by `Links::link_change`
+/// in a single request. Settings which are not set stay unchanged.
+pub struct LinkChange {
+    index: u32,
+    flags: IfFlags,
+    change: IfFlags,
+    mtu: Option<u32>,
+    name: Option<String>,
+    address: Option<MacAddr>,
+    txqlen: Option<u32>,
+    alias: Option<String>,
+    group: Option<u32>,
+    netns: Option<Netns>,
+}
+
+impl LinkChange {
+    /// Creates change of link with index `index`
+    pub fn new(index: u32) -> Self {
+        LinkChange {
+            index: index,
+            flags: IfFlags::new(0),
+            change: IfFlags::new(0),
+            mtu: None,
+            name: None,
+            address: None,
+            txqlen: None,
+            alias: None,
+            group: None,
+            netns: None,
+        }
+    }
+
+    fn set_flag(mut self, flag: IfFlags, on: bool) -> Self {
+        self.change = self.change | flag;
+        if on {
+            self.flags = self.flags | flag;
+        } else {
+            self.flags = self.flags & !flag;
+        }
+        self
+    }
+
+    /// Set link up or down
+    pub fn set_up(self, up: bool) -> Self {
+        self.set_flag(UP, up)
+    }
+
+    /// Receive all packets
+    pub fn set_promisc(self, promisc: bool) -> Self {
+        self.set_flag(PROMISC, promisc)
+    }
+
+    /// Receive all multicast packets
+    pub fn set_allmulti(self, allmulti: bool) -> Self {
+        self.set_flag(ALLMULTI, allmulti)
+    }
+
+    /// Use ARP on the link (clears NOARP flag)
+    pub fn set_arp(self, arp: bool) -> Self {
+        self.set_flag(NOARP, !arp)
+    }
+
+    pub fn set_mtu(mut self, mtu: u32) -> Self {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    /// Rename the link, most links must be down for that
+    pub fn set_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Set hardware address
+    pub fn set_address(mut self, address: MacAddr) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Set transmit queue length
+    pub fn set_txqlen(mut self, txqlen: u32) -> Self {
+        self.txqlen = Some(txqlen);
+        self
+    }
+
+    /// Set alias (ifalias), an empty one clears it
+    pub fn set_alias(mut self, alias: &str) -> Self {
+        self.alias = Some(alias.to_owned());
+        self
+    }
 
-       try!(self.write(req.packet()));
-       let reader = NetlinkReader::new(self);
-       reader.read_to_end()
+    /// Set link group
+    pub fn set_group(mut self, group: u32) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    /// Move link to another network namespace
+    pub fn set_netns(mut self, netns: Netns) -> Self {
+        self.netns = Some(netns);
+        self
     }
 
+    pub fn build(self) -> IfInfoPacket<'static> {
+        let mut data = vec![0; MutableIfInfoPacket::minimum_packet_size()];
+        {
+            let mut ifinfo = MutableIfInfoPacket::new(&mut data).unwrap();
+            ifinfo.set_family(0 /* AF_UNSPEC */);
+            ifinfo.set_index(self.index);
+            ifinfo.set_flags(self.flags);
+            ifinfo.set_change(self.change.bits());
+        }
+        if let Some(mtu) = self.mtu {
+            let mut buf = vec![0; MutableRtAttrMtuPacket::minimum_packet_size()];
+            let len = buf.len() as u16;
+            {
+                let mut rta = MutableRtAttrMtuPacket::new(&mut buf).unwrap();
+                rta.set_rta_len(len);
+                rta.set_rta_type(IFLA_MTU);
+                rta.set_mtu(mtu);
+            }
+            data.extend_from_slice(&buf);
+        }
+        if let Some(ref name) = self.name {
+            append_rta_str(&mut data, IFLA_IFNAME, name);
+        }
+        if let Some(address) = self.address {
+            append_rta(&mut data, IFLA_ADDRESS, &[address.0, address.1, address.2, address.3, address.4, address.5]);
+        }
+        if let Some(txqlen) = self.txqlen {
+            append_rta_u32(&mut data, IFLA_TXQLEN, txqlen);
+        }
+        if let Some(ref alias) = self.alias {
+            append_rta_str(&mut data, IFLA_IFALIAS, alias);
+        }
+        if let Some(group) = self.group {
+            append_rta_u32(&mut data, IFLA_GROUP, group);
+        }
+        if let Some(netns) = self.netns {
+            netns.append_to(&mut data);
+        }
+        IfInfoPacket::owned(data).unwrap()
+    }
+
+    fn build_request(self) -> NetlinkPacket<'static> {
+        NetlinkRequestBuilder::new(RTM_NEWLINK, NLM_F_ACK)
+            .append(self.build())
+            .build()
+    }
 }
 
-impl Link {
+/// Link statistics (struct rtnl_link_stats64).
+/// Counters missing from older kernels are zero.
+#[derive(Debug,Default,Clone,Copy,PartialEq,Eq)]
+pub struct LinkStats64 {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+    pub multicast: u64,
+    pub collisions: u64,
+    pub rx_length_errors: u64,
+    pub rx_over_errors: u64,
+    pub rx_crc_errors: u64,
+    pub rx_frame_errors: u64,
+    pub rx_fifo_errors: u64,
+    pub rx_missed_errors: u64,
+    pub tx_aborted_errors: u64,
+    pub tx_carrier_errors: u64,
+    pub tx_fifo_errors: u64,
+    pub tx_heartbeat_errors: u64,
+    pub tx_window_errors: u64,
+    pub rx_compressed: u64,
+    pub tx_compressed: u64,
+    pub rx_nohandler: u64,
+    pub rx_otherhost_dropped: u64,
+}
+
+impl LinkStats64 {
+    fn from_bytes(buf: &[u8]) -> Self {
+        let counter = |idx: usize| {
+            buf.get(idx * 8..idx * 8 + 8).map(NativeEndian::read_u64).unwrap_or(0)
+        };
+        LinkStats64 {
+            rx_packets: counter(0),
+            tx_packets: counter(1),
+            rx_bytes: counter(2),
+            tx_bytes: counter(3),
+            rx_errors: counter(4),
+            tx_errors: counter(5),
+            rx_dropped: counter(6),
+            tx_dropped: counter(7),
+            multicast: counter(8),
+            collisions: counter(9),
+            rx_length_errors: counter(10),
+            rx_over_errors: counter(11),
+            rx_crc_errors: counter(12),
+            rx_frame_errors: counter(13),
+            rx_fifo_errors: counter(14),
+            rx_missed_errors: counter(15),
+            tx_aborted_errors: counter(16),
+            tx_carrier_errors: counter(17),
+            tx_fifo_errors: counter(18),
+            tx_heartbeat_errors: counter(19),
+            tx_window_errors: counter(20),
+            rx_compressed: counter(21),
+            tx_compressed: counter(22),
+            rx_nohandler: counter(23),
+            rx_otherhost_dropped: counter(24),
+        }
+    }
+}
+
+impl<'a> LinkView<'a> {
+    /// Wraps RTM_NEWLINK or RTM_DELLINK message, e.g. a notification
+    pub fn from_packet(packet: NetlinkPacket<'a>) -> Option<LinkView<'a>> {
+        match packet.get_kind() {
+            RTM_NEWLINK | RTM_DELLINK if IfInfoPacket::new(packet.payload()).is_some() => Some(LinkView { packet: packet }),
+            _ => None,
+        }
+    }
+
+    /// Copies the message out of the buffer it is borrowed from
+    pub fn into_owned(self) -> Link {
+        LinkView { packet: NetlinkPacket::owned(self.packet.packet().to_owned()).unwrap() }
+    }
+
     /// Get link's unique index
     pub fn get_index(&self) -> u32 {
         self.with_ifinfo(|ifi| ifi.get_index())
@@ -391,12 +837,10 @@ impl Link {
         self.with_ifinfo(|ifi| ifi.get_flags())
     }
 
-    /// Get hardware address
+    /// Get hardware address, None if it is not a MAC address
+    /// (e.g. of a tunnel)
     pub fn get_hw_addr(&self) -> Option<MacAddr> {
-        self.with_rta(IFLA_ADDRESS, |rta| {
-            let payload = rta.payload();
-            MacAddr::new(payload[0], payload[1], payload[2], payload[3], payload[4], payload[5])
-        })
+        self.get_mac(IFLA_ADDRESS)
     }
 
     /// Get MTU
@@ -409,41 +853,154 @@ impl Link {
 
     /// Queueing discipline
     pub fn get_qdisc(&self) -> Option<String> {
-        use std::ffi::CStr;
-        self.with_rta(IFLA_QDISC, |rta| {
-            let cstr = CStr::from_bytes_with_nul(rta.payload()).unwrap();
-            cstr.to_owned().into_string().unwrap()
-        })
+        self.get_string(IFLA_QDISC)
     }
 
-    /// Get operating state
+    /// Get operating state, `Unknown` if missing or not known
     pub fn get_state(&self) -> OperState {
-        use std::mem;
-        self.with_rta_iter(|mut rti| {
-            let rta = rti.find(|rta| rta.get_rta_type() == IFLA_OPERSTATE).unwrap();
-            unsafe { mem::transmute(rta.payload()[0]) }
-        })
+        match self.get_u8(IFLA_OPERSTATE) {
+            Some(1) => OperState::NotPresent,
+            Some(2) => OperState::Down,
+            Some(3) => OperState::LowerLayerDown,
+            Some(4) => OperState::Testing,
+            Some(5) => OperState::Dormant,
+            Some(6) => OperState::Up,
+            _ => OperState::Unknown,
+        }
     }
 
-    /// Get broadcast address
+    /// Get broadcast address, None if it is not a MAC address
     pub fn get_broadcast(&self) -> Option<MacAddr> {
-        self.with_rta(IFLA_BROADCAST, |rta| {
-            let payload = rta.payload();
-            MacAddr::new(payload[0], payload[1], payload[2], payload[3], payload[4], payload[5])
-        })
+        self.get_mac(IFLA_BROADCAST)
     }
 
     /// Get name
     pub fn get_name(&self) -> Option<String> {
-        use std::ffi::CStr;
-        self.with_rta(IFLA_IFNAME, |rta| {
-            let payload = rta.payload();
-            let cstr = CStr::from_bytes_with_nul(rta.payload()).unwrap();
-            cstr.to_owned().into_string().unwrap()
-        })
+        self.get_string(IFLA_IFNAME)
+    }
+
+    /// Get link kind from IFLA_LINKINFO, e.g. "dummy"
+    pub fn get_kind(&self) -> Option<String> {
+        self.with_rta(IFLA_LINKINFO, |rta| {
+            RtAttrIterator::new(rta.payload())
+                .find(|info| info.get_rta_type() == IFLA_INFO_KIND)
+                .map(|info| read_string(info.payload()))
+        }).and_then(|kind| kind)
+    }
+
+    /// Get 64-bit statistics
+    pub fn get_stats64(&self) -> Option<LinkStats64> {
+        self.with_rta(IFLA_STATS64, |rta| LinkStats64::from_bytes(rta.payload()))
+    }
+
+    /// Get transmit queue length
+    pub fn get_txqlen(&self) -> Option<u32> {
+        self.get_u32(IFLA_TXQLEN)
+    }
+
+    /// Get index of the master device (bridge, bond, vrf)
+    pub fn get_master(&self) -> Option<u32> {
+        self.get_u32(IFLA_MASTER)
+    }
+
+    /// Get index of the lower device (e.g. parent of a vlan)
+    pub fn get_link(&self) -> Option<u32> {
+        self.get_u32(IFLA_LINK)
+    }
+
+    /// Get link group
+    pub fn get_group(&self) -> Option<u32> {
+        self.get_u32(IFLA_GROUP)
+    }
+
+    /// Get alias (ifalias)
+    pub fn get_alias(&self) -> Option<String> {
+        self.get_string(IFLA_IFALIAS)
+    }
+
+    /// Get promiscuity count
+    pub fn get_promiscuity(&self) -> Option<u32> {
+        self.get_u32(IFLA_PROMISCUITY)
+    }
+
+    /// Get number of transmit queues
+    pub fn get_num_tx_queues(&self) -> Option<u32> {
+        self.get_u32(IFLA_NUM_TX_QUEUES)
+    }
+
+    /// Get number of receive queues
+    pub fn get_num_rx_queues(&self) -> Option<u32> {
+        self.get_u32(IFLA_NUM_RX_QUEUES)
+    }
+
+    /// Get carrier state
+    pub fn get_carrier(&self) -> Option<bool> {
+        self.get_u8(IFLA_CARRIER).map(|carrier| carrier != 0)
+    }
+
+    /// Get number of carrier changes
+    pub fn get_carrier_changes(&self) -> Option<u32> {
+        self.get_u32(IFLA_CARRIER_CHANGES)
+    }
+
+    /// Get protocol down state
+    pub fn get_proto_down(&self) -> Option<bool> {
+        self.get_u8(IFLA_PROTO_DOWN).map(|down| down != 0)
+    }
+
+    /// Get maximum GSO segment count
+    pub fn get_gso_max_segs(&self) -> Option<u32> {
+        self.get_u32(IFLA_GSO_MAX_SEGS)
+    }
+
+    /// Get maximum GSO size
+    pub fn get_gso_max_size(&self) -> Option<u32> {
+        self.get_u32(IFLA_GSO_MAX_SIZE)
+    }
+
+    /// Get id of the namespace of the lower device
+    pub fn get_link_netnsid(&self) -> Option<i32> {
+        self.get_u32(IFLA_LINK_NETNSID).map(|id| id as i32)
+    }
+
+    /// Get physical port name
+    pub fn get_phys_port_name(&self) -> Option<String> {
+        self.get_string(IFLA_PHYS_PORT_NAME)
+    }
+
+    /// Get physical port id
+    pub fn get_phys_port_id(&self) -> Option<Vec<u8>> {
+        self.with_rta(IFLA_PHYS_PORT_ID, |rta| rta.payload().to_owned())
+    }
+
+    /// Get physical switch id
+    pub fn get_phys_switch_id(&self) -> Option<Vec<u8>> {
+        self.with_rta(IFLA_PHYS_SWITCH_ID, |rta| rta.payload().to_owned())
     }
 
     // helper methods
+    fn get_u8(&self, rta_type: u16) -> Option<u8> {
+        self.with_rta(rta_type, |rta| rta.payload().first().cloned()).and_then(|val| val)
+    }
+
+    fn get_u32(&self, rta_type: u16) -> Option<u32> {
+        self.with_rta(rta_type, |rta| read_u32(rta.payload())).and_then(|val| val)
+    }
+
+    fn get_string(&self, rta_type: u16) -> Option<String> {
+        self.with_rta(rta_type, |rta| read_string(rta.payload()))
+    }
+
+    fn get_mac(&self, rta_type: u16) -> Option<MacAddr> {
+        self.with_rta(rta_type, |rta| {
+            let payload = rta.payload();
+            if payload.len() != 6 {
+                return None;
+            }
+            Some(MacAddr::new(payload[0], payload[1], payload[2], payload[3], payload[4], payload[5]))
+        }).and_then(|mac| mac)
+    }
+
     fn with_packet<T,F>(&self, cb: F) -> T
         where F: Fn(&NetlinkPacket) -> T {
         cb(&self.packet)
@@ -473,12 +1030,13 @@ impl Link {
 
     // static methods
     fn get_links_iter<R: Read>(r: NetlinkBufIterator<R>) -> LinksIterator<R> {
-        //let mut conn = NetlinkConnection::new();
+        //let mut conn = NetlinkConnection::new().unwrap();
         //let mut buf = [0; 32];
         //let mut reply = conn.send(Self::dump_links_request(&mut buf));
-        LinksIterator { iter: r }
+        LinksIterator { iter: r, filter: LinkFilter::new() }
     }
 
+    #[cfg(test)]
     fn dump_link(msg: NetlinkPacket) {
         use std::ffi::CStr;
         if msg.get_kind() != RTM_NEWLINK {
@@ -584,18 +1142,34 @@ mod tests {
     fn dump_links() {
         use ::packet::netlink::NetlinkConnection;
         use ::packet::route::link::{Link,Links};
-        let mut conn = NetlinkConnection::new();
+        let mut conn = NetlinkConnection::new().unwrap();
         for link in conn.iter_links().unwrap() {
-            Link::dump_link(link.packet);
+            Link::dump_link(link.unwrap().packet);
         }
     }
 
+    #[test]
+    fn iter_links_ref() {
+        use ::packet::netlink::NetlinkConnection;
+        use ::packet::route::link::Links;
+
+        let mut conn = NetlinkConnection::new().unwrap();
+        let mut links = conn.iter_links().unwrap();
+        let mut found = false;
+        while let Some(link) = links.next_ref() {
+            if link.unwrap().get_name() == Some("lo".to_owned()) {
+                found = true;
+            }
+        }
+        assert!(found);
+    }
+
     #[test]
     fn find_lo() {
         use ::packet::netlink::NetlinkConnection;
         use ::packet::route::link::{Link,Links};
 
-        let mut conn = NetlinkConnection::new();
+        let mut conn = NetlinkConnection::new().unwrap();
         let lo0 = conn.get_link_by_name("lo").unwrap();
         assert!(lo0.is_some());
         let lo0 = lo0.unwrap();
@@ -610,15 +1184,15 @@ mod tests {
     // root permissions required
     fn create_and_delete_link() {
         use ::packet::netlink::NetlinkConnection;
-        use ::packet::route::link::{Link,Links};
+        use ::packet::route::link::Links;
 
-        let mut conn = NetlinkConnection::new();
+        let mut conn = NetlinkConnection::new().unwrap();
         conn.new_dummy_link("test1488").unwrap();
         let link = conn.get_link_by_name("test1488").unwrap().unwrap();
         assert!(link.get_name() == Some("test1488".to_owned()));
-        conn.iter_links().unwrap().find(|link| link.get_name() == Some("test1488".to_owned())).is_some();
-        conn.delete_link(link);
-        conn.iter_links().unwrap().find(|link| link.get_name() == Some("test1488".to_owned())).is_none();
+        assert!(conn.iter_links().unwrap().find(|link| link.as_ref().unwrap().get_name() == Some("test1488".to_owned())).is_some());
+        conn.delete_link(link).unwrap();
+        assert!(conn.iter_links().unwrap().find(|link| link.as_ref().unwrap().get_name() == Some("test1488".to_owned())).is_none());
     }
 
     #[test]
@@ -629,7 +1203,7 @@ mod tests {
 
         let linkname = "test1489";
 
-        let mut conn = NetlinkConnection::new();
+        let mut conn = NetlinkConnection::new().unwrap();
         conn.new_dummy_link(linkname).unwrap();
 
         let link = conn.get_link_by_name(linkname).unwrap().unwrap();
@@ -644,6 +1218,187 @@ mod tests {
         let link = conn.get_link_by_name(linkname).unwrap().unwrap();
         assert!((link.get_flags() & UP).is_empty() ); // Is down
 
-        conn.delete_link(link);
+        conn.delete_link(link).unwrap();
+    }
+
+    #[test]
+    // CAP_NET_ADMIN needed
+    fn dump_links_by_kind() {
+        use ::packet::netlink::NetlinkConnection;
+        use ::packet::route::link::{Links, LinkFilter};
+        use ::error::Result;
+
+        let mut conn = NetlinkConnection::new().unwrap();
+        conn.new_dummy_link("test1492").unwrap();
+        let link = conn.get_link_by_name("test1492").unwrap().unwrap();
+        let links = conn.dump_links_filtered(&LinkFilter::new().set_kind("dummy"), 3).unwrap();
+        assert!(links.iter().any(|link| link.get_name() == Some("test1492".to_owned())));
+        assert!(links.iter().all(|link| link.get_name() != Some("lo".to_owned())));
+        let links = conn.iter_links_filtered(&LinkFilter::new().set_kind("dummy")).unwrap()
+            .collect::<Result<Vec<_>>>().unwrap();
+        assert!(links.iter().all(|link| link.get_kind() == Some("dummy".to_owned())));
+        conn.delete_link(link).unwrap();
+    }
+
+    #[test]
+    fn link_filter_matches() {
+        use ::packet::netlink::NetlinkConnection;
+        use ::packet::route::link::{Links, LinkFilter};
+
+        let mut conn = NetlinkConnection::new().unwrap();
+        let lo = conn.get_link_by_name("lo").unwrap().unwrap();
+        assert!(LinkFilter::new().matches(&lo));
+        assert!(!LinkFilter::new().set_kind("dummy").matches(&lo));
+        assert!(!LinkFilter::new().set_master(lo.get_index()).matches(&lo));
+    }
+
+    #[test]
+    // CAP_NET_ADMIN needed
+    fn change_link() {
+        use pnet::util::MacAddr;
+        use ::packet::netlink::NetlinkConnection;
+        use ::packet::route::link::{Links, LinkChange, PROMISC, NOARP};
+
+        let mut conn = NetlinkConnection::new().unwrap();
+        conn.new_dummy_link("test1499").unwrap();
+        let index = conn.get_link_by_name("test1499").unwrap().unwrap().get_index();
+        let address = MacAddr::new(0x02, 0x14, 0x99, 0x00, 0x00, 0x01);
+        conn.link_change(LinkChange::new(index)
+                         .set_name("test1499b")
+                         .set_mtu(1400)
+                         .set_address(address)
+                         .set_txqlen(2000)
+                         .set_alias("uplink")
+                         .set_group(7)
+                         .set_promisc(true)
+                         .set_arp(false)).unwrap();
+        let link = conn.get_link_by_index(index).unwrap().unwrap();
+        assert_eq!(link.get_name(), Some("test1499b".to_owned()));
+        assert_eq!(link.get_mtu(), Some(1400));
+        assert_eq!(link.get_hw_addr(), Some(address));
+        assert_eq!(link.get_txqlen(), Some(2000));
+        assert_eq!(link.get_alias(), Some("uplink".to_owned()));
+        assert_eq!(link.get_group(), Some(7));
+        assert!(link.get_flags().contains(PROMISC));
+        assert!(link.get_flags().contains(NOARP));
+
+        conn.link_change(LinkChange::new(index).set_promisc(false).set_arp(true)).unwrap();
+        let link = conn.get_link_by_index(index).unwrap().unwrap();
+        assert!(!link.get_flags().contains(PROMISC));
+        assert!(!link.get_flags().contains(NOARP));
+        conn.delete_link(link).unwrap();
+    }
+
+    #[test]
+    fn read_link_stats() {
+        use ::packet::route::link::{IfInfoPacketBuilder,Link};
+        use ::packet::route::link::{IFLA_STATS64,IFLA_TXQLEN,IFLA_CARRIER,IFLA_IFALIAS,RTM_NEWLINK};
+        use ::packet::route::{IfInfoPacket,append_rta,append_rta_u32};
+        use ::packet::netlink::NetlinkRequestBuilder;
+        use byteorder::{ByteOrder,NativeEndian};
+        use pnet::packet::Packet;
+
+        let mut stats = vec![0; 8 * 23];
+        for i in 0..23 {
+            NativeEndian::write_u64(&mut stats[i * 8..], i as u64 + 1);
+        }
+        let mut data = IfInfoPacketBuilder::new().build().packet().to_owned();
+        append_rta(&mut data, IFLA_STATS64, &stats);
+        append_rta_u32(&mut data, IFLA_TXQLEN, 1000);
+        append_rta(&mut data, IFLA_CARRIER, &[1]);
+        append_rta(&mut data, IFLA_IFALIAS, b"uplink\0");
+        let pkt = NetlinkRequestBuilder::new(RTM_NEWLINK, 0).append(IfInfoPacket::owned(data).unwrap()).build();
+        let link = Link::from_packet(pkt).unwrap();
+
+        let stats = link.get_stats64().unwrap();
+        assert_eq!(stats.rx_packets, 1);
+        assert_eq!(stats.multicast, 9);
+        assert_eq!(stats.tx_compressed, 23);
+        assert_eq!(stats.rx_nohandler, 0);
+        assert_eq!(link.get_txqlen(), Some(1000));
+        assert_eq!(link.get_carrier(), Some(true));
+        assert_eq!(link.get_alias(), Some("uplink".to_owned()));
+        assert_eq!(link.get_master(), None);
+    }
+
+    #[test]
+    fn read_malformed_link() {
+        use ::packet::route::link::{IfInfoPacketBuilder,Link,OperState};
+        use ::packet::route::link::{IFLA_ADDRESS,IFLA_BROADCAST,IFLA_IFNAME,IFLA_QDISC,IFLA_OPERSTATE,RTM_NEWLINK};
+        use ::packet::route::{IfInfoPacket,append_rta};
+        use ::packet::netlink::NetlinkRequestBuilder;
+        use pnet::packet::Packet;
+
+        let mut data = IfInfoPacketBuilder::new().build().packet().to_owned();
+        // tunnels report IPv4 addresses here
+        append_rta(&mut data, IFLA_ADDRESS, &[192, 0, 2, 1]);
+        append_rta(&mut data, IFLA_BROADCAST, &[]);
+        append_rta(&mut data, IFLA_IFNAME, b"tun0");
+        append_rta(&mut data, IFLA_QDISC, b"noqueue\0junk");
+        append_rta(&mut data, IFLA_OPERSTATE, &[42]);
+        let pkt = NetlinkRequestBuilder::new(RTM_NEWLINK, 0).append(IfInfoPacket::owned(data).unwrap()).build();
+        let link = Link::from_packet(pkt).unwrap();
+
+        assert_eq!(link.get_hw_addr(), None);
+        assert_eq!(link.get_broadcast(), None);
+        assert_eq!(link.get_name(), Some("tun0".to_owned()));
+        assert_eq!(link.get_qdisc(), Some("noqueue".to_owned()));
+        assert_eq!(link.get_state() as u8, OperState::Unknown as u8);
+    }
+
+    #[test]
+    fn lo_stats() {
+        use ::packet::netlink::NetlinkConnection;
+        use ::packet::route::link::Links;
+
+        let mut conn = NetlinkConnection::new().unwrap();
+        let lo = conn.get_link_by_name("lo").unwrap().unwrap();
+        assert!(lo.get_stats64().is_some());
+        assert!(lo.get_num_tx_queues().unwrap() > 0);
+        assert_eq!(lo.get_master(), None);
+    }
+
+    #[test]
+    fn build_veth_request() {
+        use ::packet::route::link::{create_veth_request,Netns};
+        use ::packet::route::link::{IFLA_IFNAME,IFLA_LINKINFO,IFLA_INFO_KIND,IFLA_INFO_DATA,IFLA_NET_NS_PID,VETH_INFO_PEER};
+        use ::packet::route::{IfInfoPacket,RtAttrIterator,RtAttrPacket,read_u32};
+        use pnet::packet::Packet;
+
+        fn find<'a>(attrs: &'a [u8], rta_type: u16) -> RtAttrPacket<'a> {
+            RtAttrIterator::new(attrs).find(|rta| rta.get_rta_type() == rta_type).unwrap()
+        }
+
+        let pkt = create_veth_request("test1500", "test1501", Some(Netns::Pid(1)));
+        let ifi = IfInfoPacket::new(pkt.payload()).unwrap();
+        assert_eq!(find(ifi.payload(), IFLA_IFNAME).payload(), b"test1500\0");
+        let linkinfo = find(ifi.payload(), IFLA_LINKINFO);
+        assert_eq!(find(linkinfo.payload(), IFLA_INFO_KIND).payload(), b"veth\0");
+        let info_data = find(linkinfo.payload(), IFLA_INFO_DATA);
+        let peer = find(info_data.payload(), VETH_INFO_PEER);
+        let peer_ifi = IfInfoPacket::new(peer.payload()).unwrap();
+        assert_eq!(find(peer_ifi.payload(), IFLA_IFNAME).payload(), b"test1501\0");
+        assert_eq!(read_u32(find(peer_ifi.payload(), IFLA_NET_NS_PID).payload()), Some(1));
+    }
+
+    #[test]
+    // CAP_NET_ADMIN needed
+    fn create_veth_pair() {
+        use ::packet::netlink::NetlinkConnection;
+        use ::packet::route::link::{Links, Netns};
+        use libc;
+
+        let mut conn = NetlinkConnection::new().unwrap();
+        let pid = unsafe { libc::getpid() } as u32;
+        conn.create_veth("test1500", "test1501", Some(Netns::Pid(pid))).unwrap();
+        let link = conn.get_link_by_name("test1500").unwrap().unwrap();
+        let peer = conn.get_link_by_name("test1501").unwrap().unwrap();
+        assert_eq!(link.get_link(), Some(peer.get_index()));
+        // moving to the namespace the link is already in is a no-op
+        conn.link_set_netns(peer.get_index(), Netns::Pid(pid)).unwrap();
+        assert!(conn.get_link_by_name("test1501").unwrap().is_some());
+        // deleting one end removes the pair
+        conn.delete_link(link).unwrap();
+        assert!(conn.get_link_by_name("test1501").unwrap().is_none());
     }
 }
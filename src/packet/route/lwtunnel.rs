@@ -0,0 +1,500 @@
+//! Lightweight tunnel encapsulation of routes (RTA_ENCAP_TYPE, RTA_ENCAP),
+//! the equivalent of `ip route add ... encap`
+use packet::route::{RtAttrIterator, append_rta, append_rta_u32, read_u32};
+use ::error::{Error, Result};
+use pnet::packet::Packet;
+use byteorder::{NativeEndian, BigEndian, ByteOrder};
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/* enum lwtunnel_encap_types: linux/lwtunnel.h */
+pub const LWTUNNEL_ENCAP_NONE: u16 = 0;
+pub const LWTUNNEL_ENCAP_MPLS: u16 = 1;
+pub const LWTUNNEL_ENCAP_IP: u16 = 2;
+pub const LWTUNNEL_ENCAP_ILA: u16 = 3;
+pub const LWTUNNEL_ENCAP_IP6: u16 = 4;
+pub const LWTUNNEL_ENCAP_SEG6: u16 = 5;
+pub const LWTUNNEL_ENCAP_BPF: u16 = 6;
+pub const LWTUNNEL_ENCAP_SEG6_LOCAL: u16 = 7;
+pub const LWTUNNEL_ENCAP_RPL: u16 = 8;
+pub const LWTUNNEL_ENCAP_IOAM6: u16 = 9;
+pub const LWTUNNEL_ENCAP_XFRM: u16 = 10;
+
+/* LWTUNNEL_ENCAP_MPLS attributes: linux/mpls_iptunnel.h */
+pub const MPLS_IPTUNNEL_UNSPEC: u16 = 0;
+pub const MPLS_IPTUNNEL_DST: u16 = 1;
+pub const MPLS_IPTUNNEL_TTL: u16 = 2;
+
+/* LWTUNNEL_ENCAP_IP attributes */
+pub const LWTUNNEL_IP_UNSPEC: u16 = 0;
+pub const LWTUNNEL_IP_ID: u16 = 1;
+pub const LWTUNNEL_IP_DST: u16 = 2;
+pub const LWTUNNEL_IP_SRC: u16 = 3;
+pub const LWTUNNEL_IP_TTL: u16 = 4;
+pub const LWTUNNEL_IP_TOS: u16 = 5;
+pub const LWTUNNEL_IP_FLAGS: u16 = 6;
+pub const LWTUNNEL_IP_PAD: u16 = 7;
+pub const LWTUNNEL_IP_OPTS: u16 = 8;
+
+/* LWTUNNEL_ENCAP_IP6 attributes */
+pub const LWTUNNEL_IP6_UNSPEC: u16 = 0;
+pub const LWTUNNEL_IP6_ID: u16 = 1;
+pub const LWTUNNEL_IP6_DST: u16 = 2;
+pub const LWTUNNEL_IP6_SRC: u16 = 3;
+pub const LWTUNNEL_IP6_HOPLIMIT: u16 = 4;
+pub const LWTUNNEL_IP6_TC: u16 = 5;
+pub const LWTUNNEL_IP6_FLAGS: u16 = 6;
+pub const LWTUNNEL_IP6_PAD: u16 = 7;
+pub const LWTUNNEL_IP6_OPTS: u16 = 8;
+
+/* LWTUNNEL_ENCAP_SEG6 attributes: linux/seg6_iptunnel.h */
+pub const SEG6_IPTUNNEL_UNSPEC: u16 = 0;
+pub const SEG6_IPTUNNEL_SRH: u16 = 1;
+
+/* LWTUNNEL_ENCAP_SEG6_LOCAL attributes: linux/seg6_local.h */
+pub const SEG6_LOCAL_UNSPEC: u16 = 0;
+pub const SEG6_LOCAL_ACTION: u16 = 1;
+pub const SEG6_LOCAL_SRH: u16 = 2;
+pub const SEG6_LOCAL_TABLE: u16 = 3;
+pub const SEG6_LOCAL_NH4: u16 = 4;
+pub const SEG6_LOCAL_NH6: u16 = 5;
+pub const SEG6_LOCAL_IIF: u16 = 6;
+pub const SEG6_LOCAL_OIF: u16 = 7;
+pub const SEG6_LOCAL_BPF: u16 = 8;
+pub const SEG6_LOCAL_VRFTABLE: u16 = 9;
+pub const SEG6_LOCAL_COUNTERS: u16 = 10;
+pub const SEG6_LOCAL_FLAVORS: u16 = 11;
+
+/* seg6local actions */
+pub const SEG6_LOCAL_ACTION_UNSPEC: u32 = 0;
+pub const SEG6_LOCAL_ACTION_END: u32 = 1;
+pub const SEG6_LOCAL_ACTION_END_X: u32 = 2;
+pub const SEG6_LOCAL_ACTION_END_T: u32 = 3;
+pub const SEG6_LOCAL_ACTION_END_DX2: u32 = 4;
+pub const SEG6_LOCAL_ACTION_END_DX6: u32 = 5;
+pub const SEG6_LOCAL_ACTION_END_DX4: u32 = 6;
+pub const SEG6_LOCAL_ACTION_END_DT6: u32 = 7;
+pub const SEG6_LOCAL_ACTION_END_DT4: u32 = 8;
+pub const SEG6_LOCAL_ACTION_END_B6: u32 = 9;
+pub const SEG6_LOCAL_ACTION_END_B6_ENCAP: u32 = 10;
+pub const SEG6_LOCAL_ACTION_END_BM: u32 = 11;
+pub const SEG6_LOCAL_ACTION_END_S: u32 = 12;
+pub const SEG6_LOCAL_ACTION_END_AS: u32 = 13;
+pub const SEG6_LOCAL_ACTION_END_AM: u32 = 14;
+pub const SEG6_LOCAL_ACTION_END_BPF: u32 = 15;
+pub const SEG6_LOCAL_ACTION_END_DT46: u32 = 16;
+
+const IPV6_SRCRT_TYPE_4: u8 = 4;
+
+/// SRv6 encapsulation mode
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum Seg6Mode {
+    /// Insert segment routing header into the packet
+    Inline = 0,
+    /// Encapsulate the packet in outer IPv6 header with segment routing header
+    Encap = 1,
+    /// Encapsulate L2 frame
+    L2Encap = 2,
+    /// Encapsulate with reduced segment routing header
+    EncapRed = 3,
+    /// Encapsulate L2 frame with reduced segment routing header
+    L2EncapRed = 4,
+}
+
+impl Seg6Mode {
+    fn new(val: u32) -> Option<Self> {
+        match val {
+            0 => Some(Seg6Mode::Inline),
+            1 => Some(Seg6Mode::Encap),
+            2 => Some(Seg6Mode::L2Encap),
+            3 => Some(Seg6Mode::EncapRed),
+            4 => Some(Seg6Mode::L2EncapRed),
+            _ => None,
+        }
+    }
+}
+
+/// Route encapsulation
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Encap {
+    /// Push MPLS `labels`, the first one is the outermost
+    Mpls {
+        labels: Vec<u32>,
+        ttl: Option<u8>,
+    },
+    /// Metadata for collect-metadata (external) IPv4 tunnel devices
+    Ip {
+        id: Option<u64>,
+        dst: Option<Ipv4Addr>,
+        src: Option<Ipv4Addr>,
+        ttl: Option<u8>,
+        tos: Option<u8>,
+    },
+    /// Metadata for collect-metadata (external) IPv6 tunnel devices
+    Ip6 {
+        id: Option<u64>,
+        dst: Option<Ipv6Addr>,
+        src: Option<Ipv6Addr>,
+        hoplimit: Option<u8>,
+        tc: Option<u8>,
+    },
+    /// SRv6 encapsulation, `segments` in the order they are visited
+    Seg6 {
+        mode: Seg6Mode,
+        segments: Vec<Ipv6Addr>,
+    },
+    /// SRv6 local segment processing, e.g. `SEG6_LOCAL_ACTION_END_DT6`
+    Seg6Local(Seg6Local),
+    /// Other encapsulation type with its raw attributes
+    Other(u16, Vec<u8>),
+}
+
+/// seg6local action and its parameters
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct Seg6Local {
+    pub action: u32,
+    pub table: Option<u32>,
+    pub vrf_table: Option<u32>,
+    pub nh4: Option<Ipv4Addr>,
+    pub nh6: Option<Ipv6Addr>,
+    pub iif: Option<u32>,
+    pub oif: Option<u32>,
+    /// Segments pushed by End.B6 actions, in the order they are visited
+    pub segments: Option<Vec<Ipv6Addr>>,
+}
+
+impl Seg6Local {
+    /// Creates seg6local `action` without parameters
+    pub fn new(action: u32) -> Self {
+        Seg6Local { action: action, ..Default::default() }
+    }
+}
+
+impl Encap {
+    /// Decodes RTA_ENCAP payload `buf` of type `encap_type`
+    pub fn from_attrs(encap_type: u16, buf: &[u8]) -> Option<Encap> {
+        let attrs = || RtAttrIterator::new(buf).map(|rta| (rta.get_rta_type(), rta.payload().to_owned()));
+        let encap = match encap_type {
+            LWTUNNEL_ENCAP_MPLS => {
+                let mut labels = vec![];
+                let mut ttl = None;
+                for (type_, payload) in attrs() {
+                    match type_ {
+                        MPLS_IPTUNNEL_DST => {
+                            labels = payload.chunks(4)
+                                .filter(|entry| entry.len() == 4)
+                                .map(|entry| BigEndian::read_u32(entry) >> 12)
+                                .collect();
+                        },
+                        MPLS_IPTUNNEL_TTL => ttl = payload.first().cloned(),
+                        _ => {},
+                    }
+                }
+                Encap::Mpls { labels: labels, ttl: ttl }
+            },
+            LWTUNNEL_ENCAP_IP => {
+                let (mut id, mut dst, mut src, mut ttl, mut tos) = (None, None, None, None, None);
+                for (type_, payload) in attrs() {
+                    match type_ {
+                        LWTUNNEL_IP_ID if payload.len() == 8 => id = Some(BigEndian::read_u64(&payload)),
+                        LWTUNNEL_IP_DST => dst = ipv4(&payload),
+                        LWTUNNEL_IP_SRC => src = ipv4(&payload),
+                        LWTUNNEL_IP_TTL => ttl = payload.first().cloned(),
+                        LWTUNNEL_IP_TOS => tos = payload.first().cloned(),
+                        _ => {},
+                    }
+                }
+                Encap::Ip { id: id, dst: dst, src: src, ttl: ttl, tos: tos }
+            },
+            LWTUNNEL_ENCAP_IP6 => {
+                let (mut id, mut dst, mut src, mut hoplimit, mut tc) = (None, None, None, None, None);
+                for (type_, payload) in attrs() {
+                    match type_ {
+                        LWTUNNEL_IP6_ID if payload.len() == 8 => id = Some(BigEndian::read_u64(&payload)),
+                        LWTUNNEL_IP6_DST => dst = ipv6(&payload),
+                        LWTUNNEL_IP6_SRC => src = ipv6(&payload),
+                        LWTUNNEL_IP6_HOPLIMIT => hoplimit = payload.first().cloned(),
+                        LWTUNNEL_IP6_TC => tc = payload.first().cloned(),
+                        _ => {},
+                    }
+                }
+                Encap::Ip6 { id: id, dst: dst, src: src, hoplimit: hoplimit, tc: tc }
+            },
+            LWTUNNEL_ENCAP_SEG6 => {
+                let (_, payload) = match attrs().find(|&(type_, _)| type_ == SEG6_IPTUNNEL_SRH) {
+                    Some(attr) => attr,
+                    None => return None,
+                };
+                // struct seg6_iptunnel_encap: int mode, followed by SRH
+                let mode = match read_u32(&payload).and_then(Seg6Mode::new) {
+                    Some(mode) => mode,
+                    None => return None,
+                };
+                let mut segments = match srh_segments(&payload[4..]) {
+                    Some(segments) => segments,
+                    None => return None,
+                };
+                if mode == Seg6Mode::Inline && !segments.is_empty() {
+                    // the kernel puts the original destination there
+                    segments.pop();
+                }
+                Encap::Seg6 { mode: mode, segments: segments }
+            },
+            LWTUNNEL_ENCAP_SEG6_LOCAL => {
+                let mut local = Seg6Local::default();
+                for (type_, payload) in attrs() {
+                    match type_ {
+                        SEG6_LOCAL_ACTION => local.action = read_u32(&payload).unwrap_or(0),
+                        SEG6_LOCAL_TABLE => local.table = read_u32(&payload),
+                        SEG6_LOCAL_VRFTABLE => local.vrf_table = read_u32(&payload),
+                        SEG6_LOCAL_NH4 => local.nh4 = ipv4(&payload),
+                        SEG6_LOCAL_NH6 => local.nh6 = ipv6(&payload),
+                        SEG6_LOCAL_IIF => local.iif = read_u32(&payload),
+                        SEG6_LOCAL_OIF => local.oif = read_u32(&payload),
+                        SEG6_LOCAL_SRH => local.segments = srh_segments(&payload),
+                        _ => {},
+                    }
+                }
+                Encap::Seg6Local(local)
+            },
+            _ => Encap::Other(encap_type, buf.to_owned()),
+        };
+        Some(encap)
+    }
+
+    /// Returns encapsulation type, e.g. `LWTUNNEL_ENCAP_MPLS`
+    pub fn get_type(&self) -> u16 {
+        match *self {
+            Encap::Mpls { .. } => LWTUNNEL_ENCAP_MPLS,
+            Encap::Ip { .. } => LWTUNNEL_ENCAP_IP,
+            Encap::Ip6 { .. } => LWTUNNEL_ENCAP_IP6,
+            Encap::Seg6 { .. } => LWTUNNEL_ENCAP_SEG6,
+            Encap::Seg6Local(_) => LWTUNNEL_ENCAP_SEG6_LOCAL,
+            Encap::Other(type_, _) => type_,
+        }
+    }
+
+    /// Encodes RTA_ENCAP payload, fails if an MPLS label is above
+    /// 0xfffff or there are more than 127 segments
+    pub fn to_attrs(&self) -> Result<Vec<u8>> {
+        let mut buf = vec![];
+        match *self {
+            Encap::Mpls { ref labels, ttl } => {
+                if labels.iter().any(|&label| label > 0xfffff) {
+                    return Err(Error::InvalidArgument("MPLS label must be at most 0xfffff"));
+                }
+                let mut stack = vec![0; labels.len() * 4];
+                for (i, label) in labels.iter().enumerate() {
+                    let bos = if i == labels.len() - 1 { 1 << 8 } else { 0 };
+                    BigEndian::write_u32(&mut stack[i * 4..], (label << 12) | bos);
+                }
+                append_rta(&mut buf, MPLS_IPTUNNEL_DST, &stack);
+                if let Some(ttl) = ttl {
+                    append_rta(&mut buf, MPLS_IPTUNNEL_TTL, &[ttl]);
+                }
+            },
+            Encap::Ip { id, dst, src, ttl, tos } => {
+                if let Some(id) = id {
+                    append_rta_be64(&mut buf, LWTUNNEL_IP_ID, id);
+                }
+                if let Some(dst) = dst {
+                    append_rta(&mut buf, LWTUNNEL_IP_DST, &dst.octets());
+                }
+                if let Some(src) = src {
+                    append_rta(&mut buf, LWTUNNEL_IP_SRC, &src.octets());
+                }
+                if let Some(ttl) = ttl {
+                    append_rta(&mut buf, LWTUNNEL_IP_TTL, &[ttl]);
+                }
+                if let Some(tos) = tos {
+                    append_rta(&mut buf, LWTUNNEL_IP_TOS, &[tos]);
+                }
+            },
+            Encap::Ip6 { id, dst, src, hoplimit, tc } => {
+                if let Some(id) = id {
+                    append_rta_be64(&mut buf, LWTUNNEL_IP6_ID, id);
+                }
+                if let Some(dst) = dst {
+                    append_rta(&mut buf, LWTUNNEL_IP6_DST, &dst.octets());
+                }
+                if let Some(src) = src {
+                    append_rta(&mut buf, LWTUNNEL_IP6_SRC, &src.octets());
+                }
+                if let Some(hoplimit) = hoplimit {
+                    append_rta(&mut buf, LWTUNNEL_IP6_HOPLIMIT, &[hoplimit]);
+                }
+                if let Some(tc) = tc {
+                    append_rta(&mut buf, LWTUNNEL_IP6_TC, &[tc]);
+                }
+            },
+            Encap::Seg6 { mode, ref segments } => {
+                let mut payload = vec![0; 4];
+                NativeEndian::write_u32(&mut payload, mode as u32);
+                let mut segments = segments.clone();
+                if mode == Seg6Mode::Inline {
+                    segments.push(Ipv6Addr::from([0; 16]));
+                }
+                payload.extend_from_slice(&try!(srh(&segments)));
+                append_rta(&mut buf, SEG6_IPTUNNEL_SRH, &payload);
+            },
+            Encap::Seg6Local(ref local) => {
+                append_rta_u32(&mut buf, SEG6_LOCAL_ACTION, local.action);
+                if let Some(ref segments) = local.segments {
+                    append_rta(&mut buf, SEG6_LOCAL_SRH, &try!(srh(segments)));
+                }
+                if let Some(table) = local.table {
+                    append_rta_u32(&mut buf, SEG6_LOCAL_TABLE, table);
+                }
+                if let Some(nh4) = local.nh4 {
+                    append_rta(&mut buf, SEG6_LOCAL_NH4, &nh4.octets());
+                }
+                if let Some(nh6) = local.nh6 {
+                    append_rta(&mut buf, SEG6_LOCAL_NH6, &nh6.octets());
+                }
+                if let Some(iif) = local.iif {
+                    append_rta_u32(&mut buf, SEG6_LOCAL_IIF, iif);
+                }
+                if let Some(oif) = local.oif {
+                    append_rta_u32(&mut buf, SEG6_LOCAL_OIF, oif);
+                }
+                if let Some(vrf_table) = local.vrf_table {
+                    append_rta_u32(&mut buf, SEG6_LOCAL_VRFTABLE, vrf_table);
+                }
+            },
+            Encap::Other(_, ref attrs) => buf.extend_from_slice(attrs),
+        }
+        Ok(buf)
+    }
+}
+
+fn append_rta_be64(buf: &mut Vec<u8>, rta_type: u16, val: u64) {
+    let mut payload = [0; 8];
+    BigEndian::write_u64(&mut payload, val);
+    append_rta(buf, rta_type, &payload);
+}
+
+fn ipv4(buf: &[u8]) -> Option<Ipv4Addr> {
+    if buf.len() != 4 {
+        return None;
+    }
+    Some(Ipv4Addr::new(buf[0], buf[1], buf[2], buf[3]))
+}
+
+fn ipv6(buf: &[u8]) -> Option<Ipv6Addr> {
+    if buf.len() != 16 {
+        return None;
+    }
+    let mut octets = [0; 16];
+    octets.copy_from_slice(buf);
+    Some(Ipv6Addr::from(octets))
+}
+
+/// Builds segment routing header (struct ipv6_sr_hdr) without TLVs,
+/// the header lists segments in reverse order
+fn srh(segments: &[Ipv6Addr]) -> Result<Vec<u8>> {
+    // hdrlen counts 8 octets, two per segment, in a u8
+    if segments.len() > 127 {
+        return Err(Error::InvalidArgument("segment routing header holds at most 127 segments"));
+    }
+    let last = segments.len().saturating_sub(1) as u8;
+    let mut buf = vec![
+        0, /* nexthdr, set by the kernel */
+        (segments.len() * 2) as u8, /* hdrlen in 8 octets, without the first 8 */
+        IPV6_SRCRT_TYPE_4,
+        last, /* segments_left */
+        last, /* first_segment */
+        0, /* flags */
+        0, 0, /* tag */
+    ];
+    for segment in segments.iter().rev() {
+        buf.extend_from_slice(&segment.octets());
+    }
+    Ok(buf)
+}
+
+/// Returns segments of segment routing header `buf` in the order they are visited
+fn srh_segments(buf: &[u8]) -> Option<Vec<Ipv6Addr>> {
+    if buf.len() < 8 {
+        return None;
+    }
+    let count = buf[4] as usize + 1;
+    let segments = &buf[8..];
+    if segments.len() < count * 16 {
+        return None;
+    }
+    Some(segments[..count * 16].chunks(16).rev().filter_map(ipv6).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(encap: Encap) {
+        assert_eq!(Encap::from_attrs(encap.get_type(), &encap.to_attrs().unwrap()), Some(encap));
+    }
+
+    #[test]
+    fn mpls_labels() {
+        let encap = Encap::Mpls { labels: vec![100, 200], ttl: None };
+        assert_eq!(encap.to_attrs().unwrap(), vec![
+            0x0c, 0x00, 0x01, 0x00, 0x00, 0x06, 0x40, 0x00, 0x00, 0x0c, 0x81, 0x00,
+        ]);
+        round_trip(encap);
+        round_trip(Encap::Mpls { labels: vec![16], ttl: Some(64) });
+        round_trip(Encap::Mpls { labels: vec![0xfffff], ttl: None });
+        assert!(Encap::Mpls { labels: vec![0x100000], ttl: None }.to_attrs().is_err());
+    }
+
+    #[test]
+    fn seg6_srh() {
+        let first = Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 1);
+        let second = Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 2);
+        let encap = Encap::Seg6 { mode: Seg6Mode::Encap, segments: vec![first, second] };
+        let attrs = encap.to_attrs().unwrap();
+        // mode, then SRH with segments_left 1 and the last segment first
+        assert_eq!(&attrs[4..8], &[1, 0, 0, 0]);
+        assert_eq!(&attrs[8..16], &[0, 4, 4, 1, 1, 0, 0, 0]);
+        assert_eq!(&attrs[16..32], &second.octets());
+        round_trip(encap);
+        round_trip(Encap::Seg6 { mode: Seg6Mode::Inline, segments: vec![first] });
+    }
+
+    #[test]
+    fn seg6_too_many_segments() {
+        let segments = vec![Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 1); 127];
+        assert!(Encap::Seg6 { mode: Seg6Mode::Encap, segments: segments.clone() }.to_attrs().is_ok());
+        // inline mode adds a segment for the original destination
+        assert!(Encap::Seg6 { mode: Seg6Mode::Inline, segments: segments.clone() }.to_attrs().is_err());
+        let mut local = Seg6Local::new(SEG6_LOCAL_ACTION_END_B6);
+        local.segments = Some(vec![Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 3); 128]);
+        assert!(Encap::Seg6Local(local).to_attrs().is_err());
+    }
+
+    #[test]
+    fn seg6local_action() {
+        let mut local = Seg6Local::new(SEG6_LOCAL_ACTION_END_DT6);
+        local.table = Some(100);
+        round_trip(Encap::Seg6Local(local));
+        let mut local = Seg6Local::new(SEG6_LOCAL_ACTION_END_B6);
+        local.segments = Some(vec![Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 3)]);
+        round_trip(Encap::Seg6Local(local));
+    }
+
+    #[test]
+    fn ip_metadata() {
+        round_trip(Encap::Ip {
+            id: Some(42),
+            dst: Some(Ipv4Addr::new(192, 0, 2, 1)),
+            src: None,
+            ttl: Some(64),
+            tos: None,
+        });
+        round_trip(Encap::Ip6 {
+            id: Some(42),
+            dst: Some(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            src: None,
+            hoplimit: None,
+            tc: Some(0),
+        });
+    }
+}
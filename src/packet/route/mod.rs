@@ -13,10 +13,15 @@ use pnet::packet::Packet;
 use pnet::packet::PacketSize;
 use libc;
 use std::io::Read;
+use std::net::IpAddr;
+use byteorder::{ByteOrder,NativeEndian};
 
 pub mod addr;
 pub mod link;
+pub mod lwtunnel;
+pub mod monitor;
 pub mod neighbour;
+pub mod nexthop;
 pub mod route;
 pub mod rule;
 
@@ -47,14 +52,66 @@ impl<'a> Iterator for RtAttrIterator<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(rta) = RtAttrPacket::new(&self.buf[..]) {
             let len = rta.get_rta_len() as usize; 
-            if len < 4 {
+            if len < 4 || len > self.buf.len() {
                 return None;
             }
-            self.buf = &self.buf[align(len as usize)..];
+            self.buf = &self.buf[::std::cmp::min(align(len as usize), self.buf.len())..];
             return Some(rta);
         }
         None
     }
 }
 
+/// Appends attribute `rta_type` with `payload` to `buf`, padded to alignment
+fn append_rta(buf: &mut Vec<u8>, rta_type: u16, payload: &[u8]) {
+    let mut hdr = [0; 4];
+    NativeEndian::write_u16(&mut hdr[0..2], (hdr.len() + payload.len()) as u16);
+    NativeEndian::write_u16(&mut hdr[2..4], rta_type);
+    buf.extend_from_slice(&hdr);
+    buf.extend_from_slice(payload);
+    let aligned_len = align(buf.len());
+    buf.resize(aligned_len, 0);
+}
+
+/// Appends u32 attribute `rta_type` to `buf`
+fn append_rta_u32(buf: &mut Vec<u8>, rta_type: u16, val: u32) {
+    let mut payload = [0; 4];
+    NativeEndian::write_u32(&mut payload, val);
+    append_rta(buf, rta_type, &payload);
+}
+
+/// Appends NUL-terminated string attribute `rta_type` to `buf`
+fn append_rta_str(buf: &mut Vec<u8>, rta_type: u16, val: &str) {
+    let mut payload = val.as_bytes().to_owned();
+    payload.push(0);
+    append_rta(buf, rta_type, &payload);
+}
+
+/// Reads u32 attribute payload
+fn read_u32(buf: &[u8]) -> Option<u32> {
+    if buf.len() < 4 {
+        return None;
+    }
+    Some(NativeEndian::read_u32(buf))
+}
 
+/// Reads NUL-terminated string attribute payload, the terminator is optional
+fn read_string(buf: &[u8]) -> String {
+    let bytes = buf.split(|b| *b == 0).next().unwrap_or(buf);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Decodes address attribute payload of `family`
+fn ip_from_bytes(family: u8, bytes: &[u8]) -> Option<IpAddr> {
+    match (family, bytes.len()) {
+        (2 /* AF_INET */, 4) | (10 /* AF_INET6 */, 16) => Some(addr::Addr::ip_from_family_and_bytes(family, bytes)),
+        _ => None,
+    }
+}
+
+fn ip_octets(ip: &IpAddr) -> Vec<u8> {
+    match *ip {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    }
+}
@@ -0,0 +1,227 @@
+//! Notifications about changes of links, addresses, routes, neighbours,
+//! rules and nexthops, the equivalent of `ip monitor`
+//!
+//! # Example
+//! ```ignore
+//! use pnetlink::packet::route::monitor::{RtnetlinkMonitor,RtnetlinkEvent};
+//! use pnetlink::packet::route::monitor::{RTNLGRP_LINK,RTNLGRP_IPV4_IFADDR};
+//!
+//! let monitor = RtnetlinkMonitor::new(&[RTNLGRP_LINK, RTNLGRP_IPV4_IFADDR]).unwrap();
+//! for event in monitor {
+//!     match event.unwrap() {
+//!         RtnetlinkEvent::NewLink(link) => println!("link {:?} changed", link),
+//!         event => println!("{:?}", event),
+//!     }
+//! }
+//! ```
+use packet::route::link::{Link,RTM_NEWLINK,RTM_DELLINK};
+use packet::route::addr::{Addr,RTM_NEWADDR,RTM_DELADDR};
+use packet::route::route::{Route,RTM_NEWROUTE,RTM_DELROUTE};
+use packet::route::neighbour::{Neighbour,RTM_NEWNEIGH,RTM_DELNEIGH};
+use packet::route::rule::{Rule,RTM_NEWRULE,RTM_DELRULE};
+use packet::route::nexthop::{Nexthop,RTM_NEWNEXTHOP,RTM_DELNEXTHOP};
+use packet::netlink::{NetlinkPacket,NetlinkReader};
+use ::socket::{NetlinkSocket,NetlinkProtocol};
+use ::error::{Error,Result};
+use std::collections::VecDeque;
+use std::os::unix::io::{AsRawFd,RawFd};
+
+/* rtnetlink multicast groups (linux/rtnetlink.h) */
+pub const RTNLGRP_NONE: u32 = 0;
+pub const RTNLGRP_LINK: u32 = 1;
+pub const RTNLGRP_NOTIFY: u32 = 2;
+pub const RTNLGRP_NEIGH: u32 = 3;
+pub const RTNLGRP_TC: u32 = 4;
+pub const RTNLGRP_IPV4_IFADDR: u32 = 5;
+pub const RTNLGRP_IPV4_MROUTE: u32 = 6;
+pub const RTNLGRP_IPV4_ROUTE: u32 = 7;
+pub const RTNLGRP_IPV4_RULE: u32 = 8;
+pub const RTNLGRP_IPV6_IFADDR: u32 = 9;
+pub const RTNLGRP_IPV6_MROUTE: u32 = 10;
+pub const RTNLGRP_IPV6_ROUTE: u32 = 11;
+pub const RTNLGRP_IPV6_IFINFO: u32 = 12;
+pub const RTNLGRP_DECNET_IFADDR: u32 = 13;
+pub const RTNLGRP_DECNET_ROUTE: u32 = 15;
+pub const RTNLGRP_DECNET_RULE: u32 = 16;
+pub const RTNLGRP_IPV6_PREFIX: u32 = 18;
+pub const RTNLGRP_IPV6_RULE: u32 = 19;
+pub const RTNLGRP_ND_USEROPT: u32 = 20;
+pub const RTNLGRP_PHONET_IFADDR: u32 = 21;
+pub const RTNLGRP_PHONET_ROUTE: u32 = 22;
+pub const RTNLGRP_DCB: u32 = 23;
+pub const RTNLGRP_IPV4_NETCONF: u32 = 24;
+pub const RTNLGRP_IPV6_NETCONF: u32 = 25;
+pub const RTNLGRP_MDB: u32 = 26;
+pub const RTNLGRP_MPLS_ROUTE: u32 = 27;
+pub const RTNLGRP_NSID: u32 = 28;
+pub const RTNLGRP_MPLS_NETCONF: u32 = 29;
+pub const RTNLGRP_IPV4_MROUTE_R: u32 = 30;
+pub const RTNLGRP_IPV6_MROUTE_R: u32 = 31;
+pub const RTNLGRP_NEXTHOP: u32 = 32;
+pub const RTNLGRP_BRVLAN: u32 = 33;
+
+/// Change reported by the kernel
+#[derive(Debug)]
+pub enum RtnetlinkEvent {
+    NewLink(Link),
+    DelLink(Link),
+    NewAddr(Addr),
+    DelAddr(Addr),
+    NewRoute(Route),
+    DelRoute(Route),
+    NewNeighbour(Neighbour),
+    DelNeighbour(Neighbour),
+    NewRule(Rule),
+    DelRule(Rule),
+    NewNexthop(Nexthop),
+    DelNexthop(Nexthop),
+    /// Notification of another type
+    Other(NetlinkPacket<'static>),
+}
+
+impl RtnetlinkEvent {
+    fn from_packet(pkt: NetlinkPacket<'static>) -> Result<Self> {
+        let event = match pkt.get_kind() {
+            RTM_NEWLINK => Link::from_packet(pkt).map(RtnetlinkEvent::NewLink),
+            RTM_DELLINK => Link::from_packet(pkt).map(RtnetlinkEvent::DelLink),
+            RTM_NEWADDR => Addr::from_packet(pkt).map(RtnetlinkEvent::NewAddr),
+            RTM_DELADDR => Addr::from_packet(pkt).map(RtnetlinkEvent::DelAddr),
+            RTM_NEWROUTE => Route::from_packet(pkt).map(RtnetlinkEvent::NewRoute),
+            RTM_DELROUTE => Route::from_packet(pkt).map(RtnetlinkEvent::DelRoute),
+            RTM_NEWNEIGH => Neighbour::from_packet(pkt).map(RtnetlinkEvent::NewNeighbour),
+            RTM_DELNEIGH => Neighbour::from_packet(pkt).map(RtnetlinkEvent::DelNeighbour),
+            RTM_NEWRULE => Rule::from_packet(pkt).map(RtnetlinkEvent::NewRule),
+            RTM_DELRULE => Rule::from_packet(pkt).map(RtnetlinkEvent::DelRule),
+            RTM_NEWNEXTHOP => Nexthop::from_packet(pkt).map(RtnetlinkEvent::NewNexthop),
+            RTM_DELNEXTHOP => Nexthop::from_packet(pkt).map(RtnetlinkEvent::DelNexthop),
+            _ => return Ok(RtnetlinkEvent::Other(pkt)),
+        };
+        event.ok_or(Error::Malformed("short rtnetlink notification"))
+    }
+}
+
+/// Socket subscribed to rtnetlink multicast groups. Iterating over
+/// the monitor blocks until the next event.
+///
+/// If events arrive faster than they are read, the kernel drops them
+/// and the read fails with ENOBUFS. Reading may continue after that,
+/// but the state should be dumped again.
+pub struct RtnetlinkMonitor {
+    sock: NetlinkSocket,
+    buf: Vec<u8>,
+    events: VecDeque<Result<NetlinkPacket<'static>>>,
+}
+
+impl RtnetlinkMonitor {
+    /// Creates monitor subscribed to `groups`, e.g. `RTNLGRP_LINK`
+    pub fn new(groups: &[u32]) -> Result<Self> {
+        let sock = try!(NetlinkSocket::bind(NetlinkProtocol::Route, 0));
+        let mut monitor = RtnetlinkMonitor::from(sock);
+        for group in groups {
+            try!(monitor.add_group(*group));
+        }
+        Ok(monitor)
+    }
+
+    /// Subscribes to group `group`
+    pub fn add_group(&mut self, group: u32) -> Result<()> {
+        try!(self.sock.add_membership(group));
+        Ok(())
+    }
+
+    /// Unsubscribes from group `group`
+    pub fn drop_group(&mut self, group: u32) -> Result<()> {
+        try!(self.sock.drop_membership(group));
+        Ok(())
+    }
+
+    /// Returns socket, e.g. to set read timeout
+    pub fn get_socket_mut(&mut self) -> &mut NetlinkSocket {
+        &mut self.sock
+    }
+
+    /// Waits for the next event
+    pub fn read_event(&mut self) -> Result<RtnetlinkEvent> {
+        loop {
+            if let Some(pkt) = self.events.pop_front() {
+                return pkt.and_then(RtnetlinkEvent::from_packet);
+            }
+            // a datagram is parsed at once, an error is returned after
+            // the events preceding it and doesn't affect the following datagrams
+            let size = try!(self.sock.peek_size());
+            self.buf.resize(size, 0);
+            let len = try!(self.sock.recv(&mut self.buf));
+            self.events.extend(NetlinkReader::new(&self.buf[..len]));
+        }
+    }
+}
+
+impl From<NetlinkSocket> for RtnetlinkMonitor {
+    fn from(sock: NetlinkSocket) -> Self {
+        RtnetlinkMonitor {
+            sock: sock,
+            buf: vec![],
+            events: VecDeque::new(),
+        }
+    }
+}
+
+impl Iterator for RtnetlinkMonitor {
+    type Item = Result<RtnetlinkEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.read_event())
+    }
+}
+
+impl AsRawFd for RtnetlinkMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+#[test]
+fn keep_events_before_malformed_message() {
+    let mut monitor = RtnetlinkMonitor::from(NetlinkSocket::bind(NetlinkProtocol::Route, 0).unwrap());
+    let mut data = vec![0; 48];
+    // RTM_NEWLINK with an empty ifinfomsg, then a header with too small length
+    data[0] = 32;
+    data[4] = RTM_NEWLINK as u8;
+    data[32] = 8;
+    data[36] = RTM_NEWLINK as u8;
+    monitor.events.extend(NetlinkReader::new(&data[..]));
+    match monitor.read_event() {
+        Ok(RtnetlinkEvent::NewLink(link)) => assert_eq!(link.get_index(), 0),
+        res => panic!("unexpected result {:?}", res),
+    }
+    match monitor.read_event() {
+        Err(Error::Malformed(_)) => {},
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+// CAP_NET_ADMIN needed
+fn monitor_link_changes() {
+    use std::time::Duration;
+    use packet::netlink::NetlinkConnection;
+    use packet::route::link::Links;
+
+    let name = Some("test1490".to_owned());
+    let mut monitor = RtnetlinkMonitor::new(&[RTNLGRP_LINK]).unwrap();
+    monitor.get_socket_mut().set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut conn = NetlinkConnection::new().unwrap();
+    conn.new_dummy_link("test1490").unwrap();
+    let link = conn.get_link_by_name("test1490").unwrap().unwrap();
+    conn.delete_link(link).unwrap();
+
+    let mut created = false;
+    loop {
+        match monitor.read_event().unwrap() {
+            RtnetlinkEvent::NewLink(ref link) if link.get_name() == name => created = true,
+            RtnetlinkEvent::DelLink(ref link) if link.get_name() == name => break,
+            _ => {},
+        }
+    }
+    assert!(created);
+}
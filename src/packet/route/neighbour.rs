@@ -1,6 +1,6 @@
 //! Neighbor (ARP, etc.) operations
 use libc;
-use std::io::{self, Read, Write};
+use std::io::{Read, Write};
 use std::mem;
 use std::net::IpAddr;
 
@@ -16,6 +16,13 @@ use packet::netlink::{NLMSG_NOOP, NLMSG_ERROR, NLMSG_DONE, NLMSG_OVERRUN};
 use packet::netlink::{NetlinkBufIterator, NetlinkReader, NetlinkRequestBuilder};
 use ::socket::{NetlinkSocket, NetlinkProtocol};
 use packet::netlink::NetlinkConnection;
+use ::error::Result;
+#[cfg(feature = "async")]
+use packet::async_netlink::AsyncNetlinkConnection;
+#[cfg(feature = "async")]
+use futures::Stream;
+#[cfg(feature = "async")]
+use ::error::Error;
 use pnet::packet::MutablePacket;
 use pnet::packet::Packet;
 use pnet::packet::PacketSize;
@@ -134,32 +141,46 @@ pub enum OperState {
     Up = 6,
 }
 
-pub struct Neighbour {
-    packet: NetlinkPacket<'static>,
+/// Neighbour entry, either owned (`Neighbour`) or borrowed
+/// from a reader's buffer (`NeighboursIterator::next_ref`)
+pub struct NeighbourView<'a> {
+    packet: NetlinkPacket<'a>,
 }
 
+/// Neighbour message owning its buffer
+pub type Neighbour = NeighbourView<'static>;
+
 pub struct NeighboursIterator<R: Read> {
     iter: NetlinkBufIterator<R>,
 }
 
 impl<R: Read> Iterator for NeighboursIterator<R> {
-    type Item = Neighbour;
+    type Item = Result<Neighbour>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.iter.next() {
-            Some(pkt) => {
+        self.next_ref().map(|res| res.map(|neighbour| neighbour.into_owned()))
+    }
+}
+
+impl<R: Read> NeighboursIterator<R> {
+    /// Same as `next`, but the neighbour is borrowed from the reader's
+    /// buffer instead of being copied
+    pub fn next_ref(&mut self) -> Option<Result<NeighbourView>> {
+        match self.iter.next_ref_skipping(|_| false) {
+            Some(Ok(pkt)) => {
                 let kind = pkt.get_kind();
                 if kind != RTM_NEWNEIGH {
                     return None;
                 }
-                return Some(Neighbour { packet: pkt });
+                return Some(Ok(NeighbourView { packet: pkt }));
             }
+            Some(Err(e)) => Some(Err(e)),
             None => None,
         }
     }
 }
 
-impl ::std::fmt::Debug for Neighbour {
+impl<'a> ::std::fmt::Debug for NeighbourView<'a> {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
         let family = self.get_family();
         write!(f,
@@ -213,36 +234,23 @@ pub trait Neighbours
     /// iterate over neighbours
     fn iter_neighbours(&mut self,
                        link: Option<&Link>)
-                       -> io::Result<Box<NeighboursIterator<&mut Self>>>;
+                       -> Result<Box<NeighboursIterator<&mut Self>>>;
     // Not implemented yet.
     // delete neighbour
-    // fn delete_neighbour(&mut self, neighbour: Neighbour) -> io::Result<()>;
+    // fn delete_neighbour(&mut self, neighbour: Neighbour) -> Result<()>;
     // create neighbour
-    // fn create_neighbour(&mut self, name: &str) -> io::Result<()>;
+    // fn create_neighbour(&mut self, name: &str) -> Result<()>;
     //
 }
 
 impl Neighbours for NetlinkConnection {
     fn iter_neighbours(&mut self,
                        link: Option<&Link>)
-                       -> io::Result<Box<NeighboursIterator<&mut Self>>> {
-        // NB: This should be a IfInfoPacket because - well see rtnetlink.c in Linux - but they pun
-        // successfully.
-        //
-        let req = NetlinkRequestBuilder::new(RTM_GETNEIGH, NLM_F_DUMP)
-            .append(match link {
-                    Some(link) => {
-                        NeighbourDiscoveryPacketBuilder::new().set_ifindex(link.get_index())
-                    }
-                    _ => NeighbourDiscoveryPacketBuilder::new(),
-                }
-                .build())
-            .build();
-        try!(self.write(req.packet()));
-        let reader = NetlinkReader::new(self);
+                       -> Result<Box<NeighboursIterator<&mut Self>>> {
+        let reader = try!(self.send(dump_neighbours_request(link)));
         Ok(Box::new(NeighboursIterator { iter: reader.into_iter() }))
     }
-    // fn get_neighbour_by_index(&mut self, index: u32) -> io::Result<Option<Neighbour>> {
+    // fn get_neighbour_by_index(&mut self, index: u32) -> Result<Option<Neighbour>> {
     // let mut req = {
     // let mut buf = vec![0; MutableNeighbourDiscoveryPacket::minimum_packet_size()];
     // NetlinkRequestBuilder::new(RTM_GETNEIGH, NLM_F_ACK)
@@ -259,7 +267,7 @@ impl Neighbours for NetlinkConnection {
     // }
     //
     // #[cfg(test)]
-    // fn new_dummy_neighbour(&mut self, name: &str) -> io::Result<()> {
+    // fn new_dummy_neighbour(&mut self, name: &str) -> Result<()> {
     // let mut neigh = {
     // let mut buf = vec![0; 32];
     // let name_len = name.as_bytes().len();
@@ -275,7 +283,7 @@ impl Neighbours for NetlinkConnection {
     // reader.read_to_end()
     // }
     //
-    // fn delete_neighbour(&mut self, neighbour: Neighbour) -> io::Result<()> {
+    // fn delete_neighbour(&mut self, neighbour: Neighbour) -> Result<()> {
     // let index = neighbour.get_ifindex();
     // let mut req = {
     // let mut buf = vec![0; MutableNeighbourDiscoveryPacket::minimum_packet_size()];
@@ -295,7 +303,51 @@ impl Neighbours for NetlinkConnection {
     //
 }
 
-impl Neighbour {
+/// Asynchronous neighbours operation trait
+#[cfg(feature = "async")]
+pub trait AsyncNeighbours {
+    /// iterate over neighbours of `link`, all links if None
+    fn iter_neighbours(&self, link: Option<&Link>) -> Box<Stream<Item = Neighbour, Error = Error>>;
+}
+
+#[cfg(feature = "async")]
+impl AsyncNeighbours for AsyncNetlinkConnection {
+    fn iter_neighbours(&self, link: Option<&Link>) -> Box<Stream<Item = Neighbour, Error = Error>> {
+        Box::new(self.request(dump_neighbours_request(link))
+            .take_while(|pkt| Ok(pkt.get_kind() == RTM_NEWNEIGH))
+            .map(|pkt| Neighbour { packet: pkt }))
+    }
+}
+
+fn dump_neighbours_request(link: Option<&Link>) -> NetlinkPacket<'static> {
+    // NB: This should be a IfInfoPacket because - well see rtnetlink.c in Linux - but they pun
+    // successfully.
+    //
+    NetlinkRequestBuilder::new(RTM_GETNEIGH, NLM_F_DUMP)
+        .append(match link {
+                Some(link) => {
+                    NeighbourDiscoveryPacketBuilder::new().set_ifindex(link.get_index())
+                }
+                _ => NeighbourDiscoveryPacketBuilder::new(),
+            }
+            .build())
+        .build()
+}
+
+impl<'a> NeighbourView<'a> {
+    /// Wraps RTM_NEWNEIGH or RTM_DELNEIGH message, e.g. a notification
+    pub fn from_packet(packet: NetlinkPacket<'a>) -> Option<NeighbourView<'a>> {
+        match packet.get_kind() {
+            RTM_NEWNEIGH | RTM_DELNEIGH if NeighbourDiscoveryPacket::new(packet.payload()).is_some() => Some(NeighbourView { packet: packet }),
+            _ => None,
+        }
+    }
+
+    /// Copies the message out of the buffer it is borrowed from
+    pub fn into_owned(self) -> Neighbour {
+        NeighbourView { packet: NetlinkPacket::owned(self.packet.packet().to_owned()).unwrap() }
+    }
+
     pub fn get_family(&self) -> u8 {
         self.with_neighbour(|neigh| neigh.get_family())
     }
@@ -387,6 +439,7 @@ impl Neighbour {
         NeighboursIterator { iter: r }
     }
 
+    #[cfg(test)]
     fn dump_neighbour(msg: NetlinkPacket) {
         use std::ffi::CStr;
         if msg.get_kind() != RTM_NEWNEIGH {
@@ -476,9 +529,9 @@ mod tests {
     fn dump_neighbours() {
         use ::packet::netlink::NetlinkConnection;
         use ::packet::route::neighbour::{Neighbour, Neighbours};
-        let mut conn = NetlinkConnection::new();
+        let mut conn = NetlinkConnection::new().unwrap();
         for neighbour in conn.iter_neighbours(None).unwrap() {
-            Neighbour::dump_neighbour(neighbour.packet);
+            Neighbour::dump_neighbour(neighbour.unwrap().packet);
         }
     }
 
@@ -488,10 +541,10 @@ mod tests {
         use ::packet::route::link::{Link, Links};
         use ::packet::route::neighbour::{Neighbour, Neighbours};
 
-        let mut conn = NetlinkConnection::new();
+        let mut conn = NetlinkConnection::new().unwrap();
         let lo0 = conn.get_link_by_name("lo").unwrap().unwrap();
         for neighbour in conn.iter_neighbours(Some(&lo0)).unwrap() {
-            Neighbour::dump_neighbour(neighbour.packet);
+            Neighbour::dump_neighbour(neighbour.unwrap().packet);
         }
     }
 
@@ -502,7 +555,7 @@ mod tests {
     //        use ::packet::netlink::NetlinkConnection;
     //        use ::packet::route::neighbour::{Neighbour, Neighbours};
     //
-    //        let mut conn = NetlinkConnection::new();
+    //        let mut conn = NetlinkConnection::new().unwrap();
     //        conn.new_dummy_neighbour("test1488").unwrap();
     //        let neighbour = conn.get_neighbour_by_name("test1488").unwrap().unwrap();
     //        assert!(neighbour.get_name() == Some("test1488".to_owned()));
@@ -0,0 +1,593 @@
+//! Nexthop objects operations
+//!
+//! Since Linux 5.3 nexthops and nexthop groups can be managed as
+//! objects of their own, routes refer to them by id (`RTA_NH_ID`).
+use packet::route::{NhMsgPacket, MutableNhMsgPacket, NexthopGrpPacket, MutableNexthopGrpPacket,
+                    RtAttrIterator, RtAttrPacket};
+use packet::route::{append_rta, append_rta_u32, ip_from_bytes, ip_octets, read_u32};
+use packet::route::addr::Scope;
+use packet::route::route::{RtnhFlags, RTPROT_BOOT};
+use packet::netlink::NetlinkPacket;
+use packet::netlink::{NLM_F_ACK, NLM_F_DUMP, NLM_F_EXCL, NLM_F_CREATE, NLM_F_REPLACE};
+use packet::netlink::{NetlinkBufIterator, NetlinkReader, NetlinkRequestBuilder};
+use packet::netlink::NetlinkConnection;
+use ::error::{Error,Result};
+use pnet::packet::Packet;
+use libc;
+use byteorder::{NativeEndian, ByteOrder};
+
+use std::net::IpAddr;
+use std::io::{Read, Write};
+
+pub const RTM_NEWNEXTHOP: u16 = 104;
+pub const RTM_DELNEXTHOP: u16 = 105;
+pub const RTM_GETNEXTHOP: u16 = 106;
+
+pub const NHA_UNSPEC: u16 = 0;
+pub const NHA_ID: u16 = 1; /* u32 */
+pub const NHA_GROUP: u16 = 2; /* array of nexthop_grp */
+pub const NHA_GROUP_TYPE: u16 = 3; /* u16 */
+pub const NHA_BLACKHOLE: u16 = 4; /* flag */
+pub const NHA_OIF: u16 = 5; /* u32 */
+pub const NHA_GATEWAY: u16 = 6;
+pub const NHA_ENCAP_TYPE: u16 = 7;
+pub const NHA_ENCAP: u16 = 8;
+pub const NHA_GROUPS: u16 = 9; /* flag, dump groups only */
+pub const NHA_MASTER: u16 = 10; /* u32, dump nexthops of this master device */
+pub const NHA_FDB: u16 = 11; /* flag */
+pub const NHA_RES_GROUP: u16 = 12; /* nested */
+pub const NHA_RES_BUCKET: u16 = 13; /* nested */
+
+pub const NEXTHOP_GRP_TYPE_MPATH: u16 = 0; /* hash-threshold */
+pub const NEXTHOP_GRP_TYPE_RES: u16 = 1; /* resilient */
+
+/* NHA_RES_GROUP nested attributes */
+pub const NHA_RES_GROUP_PAD: u16 = 0;
+pub const NHA_RES_GROUP_BUCKETS: u16 = 1; /* u16 */
+pub const NHA_RES_GROUP_IDLE_TIMER: u16 = 2; /* u32, clock_t */
+pub const NHA_RES_GROUP_UNBALANCED_TIMER: u16 = 3; /* u32, clock_t */
+pub const NHA_RES_GROUP_UNBALANCED_TIME: u16 = 4; /* u64, clock_t */
+
+fn nhmsg(family: u8, protocol: u8, flags: RtnhFlags) -> Vec<u8> {
+    let mut data = vec![0; MutableNhMsgPacket::minimum_packet_size()];
+    {
+        let mut nhm = MutableNhMsgPacket::new(&mut data).unwrap();
+        nhm.set_nh_family(family);
+        nhm.set_nh_scope(Scope::Universe);
+        nhm.set_nh_protocol(protocol);
+        nhm.set_nh_flags(flags.bits() as u32);
+    }
+    data
+}
+
+fn dump_nexthops_request() -> NetlinkPacket<'static> {
+    let data = nhmsg(0 /* AF_UNSPEC */, 0, RtnhFlags::new(0));
+    NetlinkRequestBuilder::new(RTM_GETNEXTHOP, NLM_F_DUMP)
+        .append(NhMsgPacket::owned(data).unwrap())
+        .build()
+}
+
+fn nexthop_id_request(kind: u16, id: u32) -> NetlinkPacket<'static> {
+    let mut data = nhmsg(0 /* AF_UNSPEC */, 0, RtnhFlags::new(0));
+    append_rta_u32(&mut data, NHA_ID, id);
+    NetlinkRequestBuilder::new(kind, NLM_F_ACK)
+        .append(NhMsgPacket::owned(data).unwrap())
+        .build()
+}
+
+/// Nexthop object, either owned (`Nexthop`) or borrowed
+/// from a reader's buffer (`NexthopsIterator::next_ref`)
+#[derive(Debug)]
+pub struct NexthopView<'a> {
+    packet: NetlinkPacket<'a>,
+}
+
+/// Nexthop message owning its buffer
+pub type Nexthop = NexthopView<'static>;
+
+impl Nexthop {
+    /// Iterate over nexthops and nexthop groups
+    pub fn iter_nexthops(conn: &mut NetlinkConnection) -> Result<NexthopsIterator<&mut NetlinkConnection>> {
+        let reply = try!(conn.send(dump_nexthops_request()));
+        Ok(NexthopsIterator { iter: reply.into_iter() })
+    }
+
+    /// Dump all nexthops, restarting interrupted dump up to `retries` times
+    pub fn dump_nexthops(conn: &mut NetlinkConnection, retries: u32) -> Result<Vec<Nexthop>> {
+        let pkts = try!(conn.dump(dump_nexthops_request(), retries));
+        Ok(pkts.into_iter()
+            .filter(|pkt| pkt.get_kind() == RTM_NEWNEXTHOP)
+            .map(|pkt| Nexthop { packet: pkt })
+            .collect())
+    }
+}
+
+impl<'a> NexthopView<'a> {
+    /// Wraps RTM_NEWNEXTHOP or RTM_DELNEXTHOP message, e.g. a notification
+    pub fn from_packet(packet: NetlinkPacket<'a>) -> Option<NexthopView<'a>> {
+        match packet.get_kind() {
+            RTM_NEWNEXTHOP | RTM_DELNEXTHOP if NhMsgPacket::new(packet.payload()).is_some() => Some(NexthopView { packet: packet }),
+            _ => None,
+        }
+    }
+
+    /// Copies the message out of the buffer it is borrowed from
+    pub fn into_owned(self) -> Nexthop {
+        NexthopView { packet: NetlinkPacket::owned(self.packet.packet().to_owned()).unwrap() }
+    }
+
+    /// get nexthop id
+    pub fn get_id(&self) -> u32 {
+        self.get_u32(NHA_ID).unwrap_or(0)
+    }
+
+    /// get address family, AF_UNSPEC for groups
+    pub fn get_family(&self) -> u8 {
+        self.with_nhmsg(|nhm| nhm.get_nh_family())
+    }
+
+    /// get scope
+    pub fn get_scope(&self) -> Scope {
+        self.with_nhmsg(|nhm| nhm.get_nh_scope())
+    }
+
+    /// get protocol which installed the nexthop, e.g. `RTPROT_BOOT`
+    pub fn get_protocol(&self) -> u8 {
+        self.with_nhmsg(|nhm| nhm.get_nh_protocol())
+    }
+
+    /// get flags, e.g. `RTNH_F_ONLINK` or `RTNH_F_DEAD`
+    pub fn get_flags(&self) -> RtnhFlags {
+        self.with_nhmsg(|nhm| RtnhFlags::new(nhm.get_nh_flags() as u8))
+    }
+
+    /// get output interface index
+    pub fn get_oif(&self) -> Option<u32> {
+        self.get_u32(NHA_OIF)
+    }
+
+    /// get gateway address
+    pub fn get_gateway(&self) -> Option<IpAddr> {
+        let family = self.get_family();
+        self.with_rta(NHA_GATEWAY, |rta| ip_from_bytes(family, rta.payload())).and_then(|ip| ip)
+    }
+
+    /// Returns whether packets are dropped silently
+    pub fn is_blackhole(&self) -> bool {
+        self.with_rta(NHA_BLACKHOLE, |_| ()).is_some()
+    }
+
+    /// Returns whether the nexthop is used by the bridge FDB, e.g. for VXLAN
+    pub fn is_fdb(&self) -> bool {
+        self.with_rta(NHA_FDB, |_| ()).is_some()
+    }
+
+    /// get group members as (id, weight), None if not a group.
+    /// Weight is 1 to 256, or up to 65536 on Linux 6.12+
+    pub fn get_group(&self) -> Option<Vec<(u32, u32)>> {
+        self.with_rta(NHA_GROUP, |rta| {
+            rta.payload()
+                .chunks(NexthopGrpPacket::minimum_packet_size())
+                .filter_map(NexthopGrpPacket::new)
+                .map(|grp| (grp.get_id(), ((grp.get_weight_high() as u32) << 8 | grp.get_weight() as u32) + 1))
+                .collect()
+        })
+    }
+
+    /// get group type, e.g. `NEXTHOP_GRP_TYPE_RES`
+    pub fn get_group_type(&self) -> Option<u16> {
+        self.with_rta(NHA_GROUP_TYPE, |rta| {
+            if rta.payload().len() < 2 {
+                return None;
+            }
+            Some(NativeEndian::read_u16(rta.payload()))
+        }).and_then(|type_| type_)
+    }
+
+    /// get number of buckets of a resilient group
+    pub fn get_res_buckets(&self) -> Option<u16> {
+        self.with_res_group(NHA_RES_GROUP_BUCKETS, |buf| {
+            if buf.len() < 2 {
+                return None;
+            }
+            Some(NativeEndian::read_u16(buf))
+        })
+    }
+
+    /// get idle timer of a resilient group in clock ticks (USER_HZ)
+    pub fn get_res_idle_timer(&self) -> Option<u32> {
+        self.with_res_group(NHA_RES_GROUP_IDLE_TIMER, read_u32)
+    }
+
+    /// get unbalanced timer of a resilient group in clock ticks (USER_HZ)
+    pub fn get_res_unbalanced_timer(&self) -> Option<u32> {
+        self.with_res_group(NHA_RES_GROUP_UNBALANCED_TIMER, read_u32)
+    }
+
+    fn with_nhmsg<T, F>(&self, cb: F) -> T
+        where F: Fn(NhMsgPacket) -> T {
+        cb(NhMsgPacket::new(self.packet.payload()).unwrap())
+    }
+
+    fn with_rta<T, F>(&self, rta_type: u16, cb: F) -> Option<T>
+        where F: Fn(RtAttrPacket) -> T {
+        self.with_nhmsg(|nhm| {
+            RtAttrIterator::new(nhm.payload())
+                .find(|rta| rta.get_rta_type() == rta_type)
+                .map(|rta| cb(rta))
+        })
+    }
+
+    fn with_res_group<T, F>(&self, rta_type: u16, cb: F) -> Option<T>
+        where F: Fn(&[u8]) -> Option<T> {
+        self.with_rta(NHA_RES_GROUP, |rta| {
+            RtAttrIterator::new(rta.payload())
+                .find(|rta| rta.get_rta_type() == rta_type)
+                .and_then(|rta| cb(rta.payload()))
+        }).and_then(|val| val)
+    }
+
+    fn get_u32(&self, rta_type: u16) -> Option<u32> {
+        self.with_rta(rta_type, |rta| read_u32(rta.payload())).and_then(|val| val)
+    }
+
+    #[cfg(test)]
+    fn dump_nexthop(msg: NetlinkPacket<'static>) {
+        let nh = match Nexthop::from_packet(msg) {
+            Some(nh) => nh,
+            None => return,
+        };
+        println!("├ nexthop: id {} family {} proto {} flags {:?}",
+                 nh.get_id(), nh.get_family(), nh.get_protocol(), nh.get_flags());
+        if let Some(gateway) = nh.get_gateway() {
+            println!(" ├ GATEWAY {}", gateway);
+        }
+        if let Some(oif) = nh.get_oif() {
+            println!(" ├ OUT.IF {}", oif);
+        }
+        if nh.is_blackhole() {
+            println!(" ├ BLACKHOLE");
+        }
+        if nh.is_fdb() {
+            println!(" ├ FDB");
+        }
+        if let Some(group) = nh.get_group() {
+            println!(" ├ GROUP {:?} type {:?}", group, nh.get_group_type());
+        }
+    }
+}
+
+/// Nexthop objects operation trait
+pub trait Nexthops where Self: Read + Write {
+    /// returns nexthop by its id
+    fn get_nexthop(&mut self, id: u32) -> Result<Option<Nexthop>>;
+    /// add nexthop, fails if the id is already used
+    fn add_nexthop(&mut self, nexthop: NexthopBuilder) -> Result<()>;
+    /// add nexthop or replace the one with the same id, routes using it are updated
+    fn replace_nexthop(&mut self, nexthop: NexthopBuilder) -> Result<()>;
+    /// delete nexthop, routes using it are deleted too
+    fn delete_nexthop(&mut self, id: u32) -> Result<()>;
+}
+
+impl Nexthops for NetlinkConnection {
+    fn get_nexthop(&mut self, id: u32) -> Result<Option<Nexthop>> {
+        let reader = try!(self.send(nexthop_id_request(RTM_GETNEXTHOP, id)));
+        let mut last = None;
+        for nexthop in (NexthopsIterator { iter: reader.into_iter() }) {
+            match nexthop {
+                Ok(nexthop) => last = Some(nexthop),
+                Err(ref e) if e.raw_os_error() == Some(libc::ENOENT) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(last)
+    }
+
+    fn add_nexthop(&mut self, nexthop: NexthopBuilder) -> Result<()> {
+        let req = NetlinkRequestBuilder::new(RTM_NEWNEXTHOP, NLM_F_CREATE | NLM_F_EXCL | NLM_F_ACK)
+            .append(try!(nexthop.build())).build();
+        let reader = try!(self.send(req));
+        reader.read_to_end()
+    }
+
+    fn replace_nexthop(&mut self, nexthop: NexthopBuilder) -> Result<()> {
+        let req = NetlinkRequestBuilder::new(RTM_NEWNEXTHOP, NLM_F_CREATE | NLM_F_REPLACE | NLM_F_ACK)
+            .append(try!(nexthop.build())).build();
+        let reader = try!(self.send(req));
+        reader.read_to_end()
+    }
+
+    fn delete_nexthop(&mut self, id: u32) -> Result<()> {
+        let reader = try!(self.send(nexthop_id_request(RTM_DELNEXTHOP, id)));
+        reader.read_to_end()
+    }
+}
+
+/// Nexthop description for `Nexthops` operations: a gateway and/or
+/// device, a blackhole or a group of other nexthops
+pub struct NexthopBuilder {
+    id: u32,
+    family: Option<u8>,
+    protocol: u8,
+    flags: RtnhFlags,
+    oif: Option<u32>,
+    gateway: Option<IpAddr>,
+    blackhole: bool,
+    fdb: bool,
+    group: Vec<(u32, u32)>,
+    group_type: Option<u16>,
+    res_buckets: Option<u16>,
+    res_idle_timer: Option<u32>,
+    res_unbalanced_timer: Option<u32>,
+}
+
+impl NexthopBuilder {
+    /// Creates nexthop with id `id`
+    pub fn new(id: u32) -> Self {
+        NexthopBuilder {
+            id: id,
+            family: None,
+            protocol: RTPROT_BOOT,
+            flags: RtnhFlags::new(0),
+            oif: None,
+            gateway: None,
+            blackhole: false,
+            fdb: false,
+            group: vec![],
+            group_type: None,
+            res_buckets: None,
+            res_idle_timer: None,
+            res_unbalanced_timer: None,
+        }
+    }
+
+    /// Set address family, by default taken from the gateway,
+    /// AF_UNSPEC for groups and AF_INET otherwise
+    pub fn set_family(mut self, family: u8) -> Self {
+        self.family = Some(family);
+        self
+    }
+
+    pub fn set_protocol(mut self, protocol: u8) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Set flags, only `RTNH_F_ONLINK` is accepted by the kernel
+    pub fn set_flags(mut self, flags: RtnhFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn set_oif(mut self, oif: u32) -> Self {
+        self.oif = Some(oif);
+        self
+    }
+
+    pub fn set_gateway(mut self, gateway: IpAddr) -> Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    /// Drop packets silently
+    pub fn set_blackhole(mut self) -> Self {
+        self.blackhole = true;
+        self
+    }
+
+    /// Use the nexthop (or group) for bridge FDB entries only
+    pub fn set_fdb(mut self) -> Self {
+        self.fdb = true;
+        self
+    }
+
+    /// Add nexthop `id` with weight 1 to 256 to the group. Weights up
+    /// to 65536 need Linux 6.12+, older kernels reject them. Other
+    /// weights fail when the request is built.
+    pub fn add_group_member(mut self, id: u32, weight: u32) -> Self {
+        self.group.push((id, weight));
+        self
+    }
+
+    /// Make the group resilient with `buckets` hash buckets, timers
+    /// are in clock ticks (USER_HZ)
+    pub fn set_resilient(mut self, buckets: u16, idle_timer: Option<u32>, unbalanced_timer: Option<u32>) -> Self {
+        self.group_type = Some(NEXTHOP_GRP_TYPE_RES);
+        self.res_buckets = Some(buckets);
+        self.res_idle_timer = idle_timer;
+        self.res_unbalanced_timer = unbalanced_timer;
+        self
+    }
+
+    /// Builds nhmsg, fails if a group weight is 0 or above 65536
+    pub fn build(self) -> Result<NhMsgPacket<'static>> {
+        if self.group.iter().any(|&(_, weight)| weight == 0 || weight > 0x10000) {
+            return Err(Error::InvalidArgument("nexthop group weight must be 1 to 65536"));
+        }
+        let family = match (self.family, self.gateway) {
+            (Some(family), _) => family,
+            (None, Some(IpAddr::V4(_))) => 2 /* AF_INET */,
+            (None, Some(IpAddr::V6(_))) => 10 /* AF_INET6 */,
+            // the kernel accepts AF_UNSPEC only for groups
+            (None, None) if !self.group.is_empty() => 0 /* AF_UNSPEC */,
+            (None, None) => 2 /* AF_INET */,
+        };
+        let mut data = nhmsg(family, self.protocol, self.flags);
+        if self.id != 0 {
+            append_rta_u32(&mut data, NHA_ID, self.id);
+        }
+        if !self.group.is_empty() {
+            let mut group = vec![0; self.group.len() * MutableNexthopGrpPacket::minimum_packet_size()];
+            for (chunk, &(id, weight)) in group.chunks_mut(MutableNexthopGrpPacket::minimum_packet_size()).zip(&self.group) {
+                // weight is sent decremented by one
+                let weight = weight - 1;
+                let mut grp = MutableNexthopGrpPacket::new(chunk).unwrap();
+                grp.set_id(id);
+                grp.set_weight(weight as u8);
+                grp.set_weight_high((weight >> 8) as u8);
+            }
+            append_rta(&mut data, NHA_GROUP, &group);
+            let mut type_ = [0; 2];
+            NativeEndian::write_u16(&mut type_, self.group_type.unwrap_or(NEXTHOP_GRP_TYPE_MPATH));
+            append_rta(&mut data, NHA_GROUP_TYPE, &type_);
+        }
+        if let Some(buckets) = self.res_buckets {
+            let mut res = vec![];
+            let mut val = [0; 2];
+            NativeEndian::write_u16(&mut val, buckets);
+            append_rta(&mut res, NHA_RES_GROUP_BUCKETS, &val);
+            if let Some(timer) = self.res_idle_timer {
+                append_rta_u32(&mut res, NHA_RES_GROUP_IDLE_TIMER, timer);
+            }
+            if let Some(timer) = self.res_unbalanced_timer {
+                append_rta_u32(&mut res, NHA_RES_GROUP_UNBALANCED_TIMER, timer);
+            }
+            append_rta(&mut data, NHA_RES_GROUP, &res);
+        }
+        if self.blackhole {
+            append_rta(&mut data, NHA_BLACKHOLE, &[]);
+        }
+        if let Some(oif) = self.oif {
+            append_rta_u32(&mut data, NHA_OIF, oif);
+        }
+        if let Some(gateway) = self.gateway {
+            append_rta(&mut data, NHA_GATEWAY, &ip_octets(&gateway));
+        }
+        if self.fdb {
+            append_rta(&mut data, NHA_FDB, &[]);
+        }
+        Ok(NhMsgPacket::owned(data).unwrap())
+    }
+}
+
+pub struct NexthopsIterator<R: Read> {
+    iter: NetlinkBufIterator<R>,
+}
+
+impl<R: Read> Iterator for NexthopsIterator<R> {
+    type Item = Result<Nexthop>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_ref().map(|res| res.map(|nexthop| nexthop.into_owned()))
+    }
+}
+
+impl<R: Read> NexthopsIterator<R> {
+    /// Same as `next`, but the nexthop is borrowed from the reader's
+    /// buffer instead of being copied
+    pub fn next_ref(&mut self) -> Option<Result<NexthopView>> {
+        match self.iter.next_ref_skipping(|_| false) {
+            Some(Ok(pkt)) => {
+                if pkt.get_kind() != RTM_NEWNEXTHOP {
+                    return None;
+                }
+                Some(Ok(NexthopView { packet: pkt }))
+            },
+            Some(Err(e)) => Some(Err(e)),
+            None => None,
+        }
+    }
+}
+
+#[test]
+fn dump_nexthops() {
+    let mut conn = NetlinkConnection::new().unwrap();
+    for nexthop in Nexthop::iter_nexthops(&mut conn).unwrap() {
+        Nexthop::dump_nexthop(nexthop.unwrap().packet);
+    }
+}
+
+#[test]
+// CAP_NET_ADMIN needed, Linux 5.3+
+fn add_and_delete_nexthops() {
+    use std::net::Ipv4Addr;
+    use packet::route::link::Links;
+    use packet::route::route::{Route, RouteBuilder, Routes};
+
+    let mut conn = NetlinkConnection::new().unwrap();
+    conn.new_dummy_link("test1494").unwrap();
+    let link = conn.get_link_by_name("test1494").unwrap().unwrap();
+    let index = link.get_index();
+    conn.link_set_up(index).unwrap();
+
+    conn.add_nexthop(NexthopBuilder::new(1494).set_oif(index)).unwrap();
+    assert!(conn.add_nexthop(NexthopBuilder::new(1494).set_oif(index)).is_err());
+    conn.add_nexthop(NexthopBuilder::new(1495).set_blackhole()).unwrap();
+    conn.add_nexthop(NexthopBuilder::new(1496).add_group_member(1494, 2)).unwrap();
+    {
+        let nexthop = conn.get_nexthop(1494).unwrap().unwrap();
+        assert_eq!(nexthop.get_oif(), Some(index));
+        assert!(!nexthop.is_blackhole());
+        assert!(conn.get_nexthop(1495).unwrap().unwrap().is_blackhole());
+        let group = conn.get_nexthop(1496).unwrap().unwrap();
+        assert_eq!(group.get_group(), Some(vec![(1494, 2)]));
+        assert_eq!(group.get_group_type(), Some(NEXTHOP_GRP_TYPE_MPATH));
+        let nexthops = Nexthop::dump_nexthops(&mut conn, 3).unwrap();
+        assert!(nexthops.iter().any(|nh| nh.get_id() == 1496));
+    }
+
+    let dst = IpAddr::V4(Ipv4Addr::new(10, 149, 4, 0));
+    conn.add_route(RouteBuilder::new().set_dst(dst, 24).set_nh_id(1496)).unwrap();
+    {
+        let routes = Route::dump_routes(&mut conn, Some(2), 3).unwrap();
+        let route = routes.iter().find(|route| route.get_dst() == Some(dst)).unwrap();
+        assert_eq!(route.get_nh_id(), Some(1496));
+    }
+    conn.delete_nexthop(1496).unwrap();
+    conn.delete_nexthop(1495).unwrap();
+    conn.delete_nexthop(1494).unwrap();
+    assert!(conn.get_nexthop(1494).unwrap().is_none());
+    conn.delete_link(link).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv6Addr;
+
+    fn parse(nexthop: NexthopBuilder) -> Nexthop {
+        let pkt = NetlinkRequestBuilder::new(RTM_NEWNEXTHOP, NLM_F_ACK).append(nexthop.build().unwrap()).build();
+        Nexthop::from_packet(pkt).unwrap()
+    }
+
+    #[test]
+    fn gateway_round_trip() {
+        let gateway = IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+        let nexthop = parse(NexthopBuilder::new(7).set_oif(2).set_gateway(gateway));
+        assert_eq!(nexthop.get_id(), 7);
+        assert_eq!(nexthop.get_family(), 10);
+        assert_eq!(nexthop.get_protocol(), RTPROT_BOOT);
+        assert_eq!(nexthop.get_oif(), Some(2));
+        assert_eq!(nexthop.get_gateway(), Some(gateway));
+        assert!(nexthop.get_group().is_none());
+    }
+
+    #[test]
+    fn blackhole_defaults_to_inet() {
+        let nexthop = parse(NexthopBuilder::new(7).set_blackhole());
+        assert_eq!(nexthop.get_family(), 2);
+        assert!(nexthop.is_blackhole());
+    }
+
+    #[test]
+    fn resilient_group_round_trip() {
+        let group = parse(NexthopBuilder::new(10)
+                          .add_group_member(7, 1)
+                          .add_group_member(8, 256)
+                          .set_resilient(128, Some(120), None));
+        assert_eq!(group.get_family(), 0);
+        assert_eq!(group.get_group(), Some(vec![(7, 1), (8, 256)]));
+        assert_eq!(group.get_group_type(), Some(NEXTHOP_GRP_TYPE_RES));
+        assert_eq!(group.get_res_buckets(), Some(128));
+        assert_eq!(group.get_res_idle_timer(), Some(120));
+        assert_eq!(group.get_res_unbalanced_timer(), None);
+    }
+
+    #[test]
+    fn group_weight_range() {
+        let group = parse(NexthopBuilder::new(10).add_group_member(7, 65536));
+        assert_eq!(group.get_group(), Some(vec![(7, 65536)]));
+        assert!(NexthopBuilder::new(10).add_group_member(7, 0).build().is_err());
+        assert!(NexthopBuilder::new(10).add_group_member(7, 65537).build().is_err());
+    }
+}
@@ -1,22 +1,35 @@
 //! Route operations
-use packet::route::{RouteCacheInfoPacket, RtMsgPacket, MutableRtMsgPacket, MutableIfInfoPacket,
+use packet::route::{RouteCacheInfoPacket, RtMsgPacket, MutableRtMsgPacket,
                     RtAttrIterator, RtAttrPacket, MutableRtAttrPacket};
+use packet::route::{RtNextHopPacket, MutableRtNextHopPacket};
+use packet::route::{align, append_rta, append_rta_str, append_rta_u32, ip_from_bytes, ip_octets, read_string, read_u32};
 use packet::route::link::Link;
+use packet::route::lwtunnel::Encap;
+use packet::route::addr::Scope;
+use packet::route::RouteCacheInfo;
 use packet::netlink::{MutableNetlinkPacket, NetlinkPacket, NetlinkErrorPacket};
 use packet::netlink::{NLM_F_ACK, NLM_F_REQUEST, NLM_F_DUMP, NLM_F_MATCH, NLM_F_EXCL, NLM_F_CREATE};
+use packet::netlink::{NLM_F_REPLACE, NLM_F_APPEND};
 use packet::netlink::{NLMSG_NOOP, NLMSG_ERROR, NLMSG_DONE, NLMSG_OVERRUN};
 use packet::netlink::{NetlinkBufIterator, NetlinkReader, NetlinkRequestBuilder};
 use socket::{NetlinkSocket, NetlinkProtocol};
 use packet::netlink::NetlinkConnection;
+use ::error::{Error,Result};
+#[cfg(feature = "async")]
+use packet::async_netlink::AsyncNetlinkConnection;
+#[cfg(feature = "async")]
+use futures::Stream;
 use pnet::packet::MutablePacket;
 use pnet::packet::Packet;
 use pnet::packet::PacketSize;
+use pnet::packet::FromPacket;
 use pnet::util::MacAddr;
 use libc;
 
-use std::net::Ipv4Addr;
-use std::io::{self, Read, Cursor};
-use byteorder::{LittleEndian, BigEndian, ReadBytesExt};
+use std::net::{Ipv4Addr,IpAddr};
+use byteorder::{NativeEndian, ByteOrder};
+use std::cmp;
+use std::io::{Read, Write};
 
 pub const RTM_NEWROUTE: u16 = 24;
 pub const RTM_DELROUTE: u16 = 25;
@@ -30,38 +43,137 @@ pub const RT_TABLE_DEFAULT: u32 = 253;
 pub const RT_TABLE_MAIN: u32 = 254;
 pub const RT_TABLE_LOCAL: u32 = 255;
 
+/* rtm_protocol: who installed the route */
+pub const RTPROT_UNSPEC: u8 = 0;
+pub const RTPROT_REDIRECT: u8 = 1; /* Route installed by ICMP redirects */
+pub const RTPROT_KERNEL: u8 = 2; /* Route installed by kernel */
+pub const RTPROT_BOOT: u8 = 3; /* Route installed during boot */
+pub const RTPROT_STATIC: u8 = 4; /* Route installed by administrator */
+pub const RTPROT_RA: u8 = 9; /* RDISC/ND router advertisements */
+pub const RTPROT_DHCP: u8 = 16; /* DHCP client */
+
+/// Route type (rtm_type)
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
 #[repr(u8)]
-enum RtmType {
-    UNICAST, // Gateway or direct route
-    LOCAL, // Accept locally
-    BROADCAST, /* Accept locally as broadcast,
-                * send as broadcast */
-    ANYCAST, /* Accept locally as broadcast,
-              * but send as unicast */
-    MULTICAST, // Multicast route
-    BLACKHOLE, // Drop
-    UNREACHABLE, // Destination is unreachable
-    PROHIBIT, // Administratively prohibited
-    THROW, // Not in this table
-    NAT, // Translate this address
-    XRESOLVE, // Use external resolver
+pub enum RtmType {
+    UNSPEC = 0,
+    UNICAST = 1, // Gateway or direct route
+    LOCAL = 2, // Accept locally
+    BROADCAST = 3, /* Accept locally as broadcast,
+                    * send as broadcast */
+    ANYCAST = 4, /* Accept locally as broadcast,
+                  * but send as unicast */
+    MULTICAST = 5, // Multicast route
+    BLACKHOLE = 6, // Drop
+    UNREACHABLE = 7, // Destination is unreachable
+    PROHIBIT = 8, // Administratively prohibited
+    THROW = 9, // Not in this table
+    NAT = 10, // Translate this address
+    XRESOLVE = 11, // Use external resolver
+}
+
+impl RtmType {
+    /// Unknown types are returned as `UNSPEC`
+    pub fn new(val: u8) -> Self {
+        match val {
+            1 => RtmType::UNICAST,
+            2 => RtmType::LOCAL,
+            3 => RtmType::BROADCAST,
+            4 => RtmType::ANYCAST,
+            5 => RtmType::MULTICAST,
+            6 => RtmType::BLACKHOLE,
+            7 => RtmType::UNREACHABLE,
+            8 => RtmType::PROHIBIT,
+            9 => RtmType::THROW,
+            10 => RtmType::NAT,
+            11 => RtmType::XRESOLVE,
+            _ => RtmType::UNSPEC,
+        }
+    }
 }
 
 bitflags! {
-    pub flags RtmFlags: u8 {
+    /// Route flags (rtm_flags)
+    pub flags RtmFlags: u32 {
+        /// Notify user of route change
         const NOTIFY = 0x100,
+        /// This route is cloned
         const CLONED = 0x200,
+        /// Multipath equalizer
         const EQUALIZE = 0x400,
+        /// Prefix addresses
         const PREFIX = 0x800,
+        /// Set rtm_table to FIB lookup result
+        const LOOKUP_TABLE = 0x1000,
+        /// Return full fib lookup match
+        const FIB_MATCH = 0x2000,
+        /// Route is offloaded
+        const OFFLOAD = 0x4000,
+        /// Route is trapping packets
+        const TRAP = 0x8000,
+        /// Route offload failed
+        const OFFLOAD_FAILED = 0x20000000,
     }
 }
 
 impl RtmFlags {
-    pub fn new(val: u8) -> Self {
+    pub fn new(val: u32) -> Self {
         RtmFlags::from_bits_truncate(val)
     }
 }
 
+/* RTA_METRICS nested attributes (linux/rtnetlink.h) */
+pub const RTAX_UNSPEC: u16 = 0;
+pub const RTAX_LOCK: u16 = 1;
+pub const RTAX_MTU: u16 = 2;
+pub const RTAX_WINDOW: u16 = 3;
+pub const RTAX_RTT: u16 = 4;
+pub const RTAX_RTTVAR: u16 = 5;
+pub const RTAX_SSTHRESH: u16 = 6;
+pub const RTAX_CWND: u16 = 7;
+pub const RTAX_ADVMSS: u16 = 8;
+pub const RTAX_REORDERING: u16 = 9;
+pub const RTAX_HOPLIMIT: u16 = 10;
+pub const RTAX_INITCWND: u16 = 11;
+pub const RTAX_FEATURES: u16 = 12;
+pub const RTAX_RTO_MIN: u16 = 13;
+pub const RTAX_INITRWND: u16 = 14;
+pub const RTAX_QUICKACK: u16 = 15;
+pub const RTAX_CC_ALGO: u16 = 16;
+pub const RTAX_FASTOPEN_NO_COOKIE: u16 = 17;
+
+/* RTAX_FEATURES bits */
+pub const RTAX_FEATURE_ECN: u32 = 1 << 0;
+pub const RTAX_FEATURE_SACK: u32 = 1 << 1;
+pub const RTAX_FEATURE_TIMESTAMP: u32 = 1 << 2;
+pub const RTAX_FEATURE_ALLFRAG: u32 = 1 << 3;
+
+bitflags! {
+    /// Nexthop flags (rtnh_flags)
+    pub flags RtnhFlags: u8 {
+        /// Nexthop is dead (used by multipath)
+        const RTNH_F_DEAD = 1,
+        /// Do recursive gateway lookup
+        const RTNH_F_PERVASIVE = 2,
+        /// Gateway is forced on link
+        const RTNH_F_ONLINK = 4,
+        /// Offloaded route
+        const RTNH_F_OFFLOAD = 8,
+        /// Carrier-down on nexthop
+        const RTNH_F_LINKDOWN = 16,
+        /// The entry is unresolved (ipmr)
+        const RTNH_F_UNRESOLVED = 32,
+        /// Nexthop is trapping packets
+        const RTNH_F_TRAP = 64,
+    }
+}
+
+impl RtnhFlags {
+    pub fn new(val: u8) -> Self {
+        RtnhFlags::from_bits_truncate(val)
+    }
+}
+
 pub const RTA_UNSPEC: u16 = 0;
 pub const RTA_DST: u16 = 1;
 pub const RTA_SRC: u16 = 2;
@@ -79,106 +191,1319 @@ pub const RTA_SESSION: u16 = 13; /* no longer used */
 pub const RTA_MP_ALGO: u16 = 14; /* no longer used */
 pub const RTA_TABLE: u16 = 15;
 pub const RTA_MARK: u16 = 16;
+pub const RTA_MFC_STATS: u16 = 17;
+pub const RTA_VIA: u16 = 18;
+pub const RTA_NEWDST: u16 = 19;
+pub const RTA_PREF: u16 = 20;
+pub const RTA_ENCAP_TYPE: u16 = 21;
+pub const RTA_ENCAP: u16 = 22;
+pub const RTA_EXPIRES: u16 = 23;
+pub const RTA_PAD: u16 = 24;
+pub const RTA_UID: u16 = 25;
+pub const RTA_TTL_PROPAGATE: u16 = 26;
+pub const RTA_IP_PROTO: u16 = 27;
+pub const RTA_SPORT: u16 = 28;
+pub const RTA_DPORT: u16 = 29;
+pub const RTA_NH_ID: u16 = 30;
+
+fn dump_routes_request(filter: &RouteFilter) -> NetlinkPacket<'static> {
+    let mut data = vec![0; MutableRtMsgPacket::minimum_packet_size()];
+    {
+        let mut rtm = MutableRtMsgPacket::new(&mut data).unwrap();
+        rtm.set_rtm_family(filter.family.unwrap_or(0 /* AF_UNSPEC */));
+        rtm.set_rtm_protocol(filter.protocol.unwrap_or(RTPROT_UNSPEC));
+        rtm.set_rtm_type(filter.type_.unwrap_or(RtmType::UNSPEC));
+    }
+    if let Some(table) = filter.table {
+        append_rta_u32(&mut data, RTA_TABLE, table);
+    }
+    if let Some(oif) = filter.oif {
+        append_rta_u32(&mut data, RTA_OIF, oif);
+    }
+    NetlinkRequestBuilder::new(RTM_GETROUTE, NLM_F_DUMP)
+        .append(RtMsgPacket::owned(data).unwrap())
+        .build()
+}
+
+/// Route dump filter. The kernel applies it only if strict checking
+/// is enabled with `SockOpt::GetStrictChk` (Linux 4.20+), otherwise
+/// the routes are filtered after they are received.
+#[derive(Debug,Clone,Default)]
+pub struct RouteFilter {
+    family: Option<u8>,
+    table: Option<u32>,
+    protocol: Option<u8>,
+    type_: Option<RtmType>,
+    oif: Option<u32>,
+}
+
+impl RouteFilter {
+    /// Creates filter matching all routes
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_family(mut self, family: u8) -> Self {
+        self.family = Some(family);
+        self
+    }
+
+    pub fn set_table(mut self, table: u32) -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    /// Set protocol, e.g. `RTPROT_STATIC`
+    pub fn set_protocol(mut self, protocol: u8) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    pub fn set_type(mut self, type_: RtmType) -> Self {
+        self.type_ = Some(type_);
+        self
+    }
+
+    /// Set output interface, matches multipath routes with a nexthop on it too
+    pub fn set_oif(mut self, oif: u32) -> Self {
+        self.oif = Some(oif);
+        self
+    }
+
+    /// Returns whether `route` passes the filter
+    pub fn matches(&self, route: &RouteView) -> bool {
+        self.family.map_or(true, |family| route.get_family() == family) &&
+            self.table.map_or(true, |table| route.get_table() == table) &&
+            self.protocol.map_or(true, |protocol| route.get_protocol() == protocol) &&
+            self.type_.map_or(true, |type_| route.get_type() == type_) &&
+            self.oif.map_or(true, |oif| {
+                let mut found = route.get_oif() == Some(oif);
+                if !found {
+                    route.for_each_nexthop(|nh| {
+                        found = nh.get_ifindex() == oif;
+                        !found
+                    });
+                }
+                found
+            })
+    }
+}
+
+fn route_get_request(dst: IpAddr, src: Option<IpAddr>, iif: Option<u32>, oif: Option<u32>,
+                     mark: Option<u32>, uid: Option<u32>, flags: RtmFlags) -> NetlinkPacket<'static> {
+    let mut data = vec![0; MutableRtMsgPacket::minimum_packet_size()];
+    {
+        let mut rtm = MutableRtMsgPacket::new(&mut data).unwrap();
+        let (family, len) = match dst {
+            IpAddr::V4(_) => (2 /* AF_INET */, 32),
+            IpAddr::V6(_) => (10 /* AF_INET6 */, 128),
+        };
+        rtm.set_rtm_family(family);
+        rtm.set_rtm_dst_len(len);
+        if src.is_some() {
+            rtm.set_rtm_src_len(len);
+        }
+        rtm.set_rtm_flags(flags);
+    }
+    append_rta(&mut data, RTA_DST, &ip_octets(&dst));
+    if let Some(src) = src {
+        append_rta(&mut data, RTA_SRC, &ip_octets(&src));
+    }
+    if let Some(iif) = iif {
+        append_rta_u32(&mut data, RTA_IIF, iif);
+    }
+    if let Some(oif) = oif {
+        append_rta_u32(&mut data, RTA_OIF, oif);
+    }
+    if let Some(mark) = mark {
+        append_rta_u32(&mut data, RTA_MARK, mark);
+    }
+    if let Some(uid) = uid {
+        append_rta_u32(&mut data, RTA_UID, uid);
+    }
+    NetlinkRequestBuilder::new(RTM_GETROUTE, NLM_F_ACK)
+        .append(RtMsgPacket::owned(data).unwrap())
+        .build()
+}
 
+/// Route message, either owned (`Route`) or borrowed
+/// from a reader's buffer (`RoutesIterator::next_ref`)
 #[derive(Debug)]
-pub struct Route {
-    packet: NetlinkPacket<'static>,
+pub struct RouteView<'a> {
+    packet: NetlinkPacket<'a>,
 }
 
+/// Route message owning its buffer
+pub type Route = RouteView<'static>;
+
 impl Route {
-    /// Iterate over routes
-    pub fn iter_routes(conn: &mut NetlinkConnection) -> RoutesIterator<&mut NetlinkConnection> {
-        let mut buf = vec![0; MutableIfInfoPacket::minimum_packet_size()];
-        let req = NetlinkRequestBuilder::new(RTM_GETROUTE, NLM_F_DUMP)
-            .append({
-                let mut ifinfo = MutableIfInfoPacket::new(&mut buf).unwrap();
-                ifinfo.set_family(0 /* AF_UNSPEC */);
-                ifinfo
-            })
-            .build();
-        let mut reply = conn.send(req);
-        RoutesIterator { iter: reply.into_iter() }
-    }
-
-    fn dump_route(msg: NetlinkPacket) {
-        use std::ffi::CStr;
-        if msg.get_kind() != RTM_NEWROUTE {
-            return;
-        }
-        // println!("NetLink pkt {:?}", msg);
-        if let Some(rtm) = RtMsgPacket::new(&msg.payload()[0..]) {
-            println!("├ rtm: {:?}", rtm);
-            let payload = &rtm.payload()[0..];
-            let iter = RtAttrIterator::new(payload);
-            for rta in iter {
-                match rta.get_rta_type() {
-                    RTA_TABLE => {
-                        let mut cur = Cursor::new(rta.payload());
-                        let table = cur.read_u32::<LittleEndian>().unwrap();
-                        println!(" ├ TABLE {:?}", table);
-                    }
-                    RTA_OIF => {
-                        let mut cur = Cursor::new(rta.payload());
-                        let idx = cur.read_u32::<LittleEndian>().unwrap();
-                        println!(" ├ OUT.IF {:?}", idx);
-                    }
-                    RTA_PRIORITY => {
-                        let mut cur = Cursor::new(rta.payload());
-                        let prio = cur.read_u32::<LittleEndian>().unwrap();
-                        println!(" ├ PRIO {:?}", prio);
-                    }
-                    RTA_GATEWAY => {
-                        let mut cur = Cursor::new(rta.payload());
-                        let ip = Ipv4Addr::from(cur.read_u32::<BigEndian>().unwrap());
-                        println!(" ├ GATEWAY {:?}", ip);
-                    }
-                    RTA_CACHEINFO => {
-                        let pkt = RouteCacheInfoPacket::new(rta.payload());
-                        println!(" ├ CACHE INFO {:?}", pkt);
-                    }
-                    RTA_SRC => println!(" ├ SRC {:?}", rta.payload()),
-                    RTA_PREFSRC => {
-                        let mut cur = Cursor::new(rta.payload());
-                        let ip = Ipv4Addr::from(cur.read_u32::<BigEndian>().unwrap());
-                        println!(" ├ PREFSRC {:?}", ip);
-                    }
-                    RTA_DST => {
-                        let mut cur = Cursor::new(rta.payload());
-                        let ip = Ipv4Addr::from(cur.read_u32::<BigEndian>().unwrap());
-                        println!(" ├ DST {:?}", ip);
-                    }
-                    _ => println!(" ├ {:?}", rta),
+    /// Iterate over `family` routes, all families if None
+    pub fn iter_routes(conn: &mut NetlinkConnection, family: Option<u8>) -> Result<RoutesIterator<&mut NetlinkConnection>> {
+        Route::iter_routes_filtered(conn, RouteFilter { family: family, ..Default::default() })
+    }
+
+    /// Iterate over routes matching `filter`
+    pub fn iter_routes_filtered(conn: &mut NetlinkConnection, filter: RouteFilter) -> Result<RoutesIterator<&mut NetlinkConnection>> {
+        let reply = try!(conn.send(dump_routes_request(&filter)));
+        Ok(RoutesIterator { iter: reply.into_iter(), filter: filter })
+    }
+
+    /// Dump all `family` routes, restarting interrupted dump up to `retries` times
+    pub fn dump_routes(conn: &mut NetlinkConnection, family: Option<u8>, retries: u32) -> Result<Vec<Route>> {
+        Route::dump_routes_filtered(conn, &RouteFilter { family: family, ..Default::default() }, retries)
+    }
+
+    /// Dump routes matching `filter`, restarting interrupted dump up to `retries` times
+    pub fn dump_routes_filtered(conn: &mut NetlinkConnection, filter: &RouteFilter, retries: u32) -> Result<Vec<Route>> {
+        let pkts = try!(conn.dump(dump_routes_request(filter), retries));
+        Ok(pkts.into_iter()
+            .filter(|pkt| pkt.get_kind() == RTM_NEWROUTE)
+            .map(|pkt| Route { packet: pkt })
+            .filter(|route| filter.matches(route))
+            .collect())
+    }
+}
+
+impl<'a> RouteView<'a> {
+    /// Wraps RTM_NEWROUTE or RTM_DELROUTE message, e.g. a notification
+    pub fn from_packet(packet: NetlinkPacket<'a>) -> Option<RouteView<'a>> {
+        match packet.get_kind() {
+            RTM_NEWROUTE | RTM_DELROUTE if RtMsgPacket::new(packet.payload()).is_some() => Some(RouteView { packet: packet }),
+            _ => None,
+        }
+    }
+
+    /// Copies the message out of the buffer it is borrowed from
+    pub fn into_owned(self) -> Route {
+        RouteView { packet: NetlinkPacket::owned(self.packet.packet().to_owned()).unwrap() }
+    }
+
+    /// get address family
+    pub fn get_family(&self) -> u8 {
+        self.with_rtmsg(|rtm| rtm.get_rtm_family())
+    }
+
+    /// get destination prefix, None for default route
+    pub fn get_dst(&self) -> Option<IpAddr> {
+        self.get_ip(RTA_DST)
+    }
+
+    /// get destination prefix length
+    pub fn get_dst_len(&self) -> u8 {
+        self.with_rtmsg(|rtm| rtm.get_rtm_dst_len())
+    }
+
+    /// get destination prefix as (address, length), None for default route
+    pub fn get_dst_prefix(&self) -> Option<(IpAddr, u8)> {
+        self.get_dst().map(|dst| (dst, self.get_dst_len()))
+    }
+
+    /// get source prefix (source routing)
+    pub fn get_src(&self) -> Option<IpAddr> {
+        self.get_ip(RTA_SRC)
+    }
+
+    /// get source prefix length
+    pub fn get_src_len(&self) -> u8 {
+        self.with_rtmsg(|rtm| rtm.get_rtm_src_len())
+    }
+
+    /// get source prefix as (address, length)
+    pub fn get_src_prefix(&self) -> Option<(IpAddr, u8)> {
+        self.get_src().map(|src| (src, self.get_src_len()))
+    }
+
+    /// get TOS
+    pub fn get_tos(&self) -> u8 {
+        self.with_rtmsg(|rtm| rtm.get_rtm_tos())
+    }
+
+    /// get routing table id, tables above 255 are only in RTA_TABLE
+    pub fn get_table(&self) -> u32 {
+        match self.with_rta(RTA_TABLE, |rta| read_u32(rta.payload())) {
+            Some(Some(table)) => table,
+            _ => self.with_rtmsg(|rtm| rtm.get_rtm_table() as u32),
+        }
+    }
+
+    /// get protocol which installed the route, e.g. `RTPROT_KERNEL`
+    pub fn get_protocol(&self) -> u8 {
+        self.with_rtmsg(|rtm| rtm.get_rtm_protocol())
+    }
+
+    /// get scope
+    pub fn get_scope(&self) -> Scope {
+        self.with_rtmsg(|rtm| rtm.get_rtm_scope())
+    }
+
+    /// get route type
+    pub fn get_type(&self) -> RtmType {
+        self.with_rtmsg(|rtm| rtm.get_rtm_type())
+    }
+
+    /// get route flags
+    pub fn get_flags(&self) -> RtmFlags {
+        self.with_rtmsg(|rtm| rtm.get_rtm_flags())
+    }
+
+    /// get gateway address
+    pub fn get_gateway(&self) -> Option<IpAddr> {
+        self.get_ip(RTA_GATEWAY)
+    }
+
+    /// get output interface index
+    pub fn get_oif(&self) -> Option<u32> {
+        self.get_u32(RTA_OIF)
+    }
+
+    /// get input interface index
+    pub fn get_iif(&self) -> Option<u32> {
+        self.get_u32(RTA_IIF)
+    }
+
+    /// get priority (metric)
+    pub fn get_priority(&self) -> Option<u32> {
+        self.get_u32(RTA_PRIORITY)
+    }
+
+    /// get preferred source address
+    pub fn get_prefsrc(&self) -> Option<IpAddr> {
+        self.get_ip(RTA_PREFSRC)
+    }
+
+    /// get firewall mark
+    pub fn get_mark(&self) -> Option<u32> {
+        self.get_u32(RTA_MARK)
+    }
+
+    /// get nexthops of a multipath route
+    pub fn get_multipath(&self) -> Option<Vec<NextHop>> {
+        let mut nexthops = vec![];
+        if !self.for_each_nexthop(|nexthop| {
+            nexthops.push(nexthop);
+            true
+        }) {
+            return None;
+        }
+        Some(nexthops)
+    }
+
+    /// Passes nexthops of a multipath route to `cb` until it returns
+    /// false, returns false if there is no RTA_MULTIPATH
+    fn for_each_nexthop<F>(&self, mut cb: F) -> bool
+        where F: FnMut(NextHop) -> bool {
+        let family = self.get_family();
+        self.with_rta(RTA_MULTIPATH, |rta| {
+            let mut buf = rta.payload();
+            while let Some(rtnh) = RtNextHopPacket::new(buf) {
+                let len = rtnh.get_rtnh_len() as usize;
+                if len < RtNextHopPacket::minimum_packet_size() || len > buf.len() {
+                    break;
                 }
+                let gateway = RtAttrIterator::new(rtnh.payload())
+                    .find(|rta| rta.get_rta_type() == RTA_GATEWAY)
+                    .and_then(|rta| ip_from_bytes(family, rta.payload()));
+                let more = cb(NextHop {
+                    ifindex: rtnh.get_rtnh_ifindex(),
+                    gateway: gateway,
+                    weight: rtnh.get_rtnh_hops() as u16 + 1,
+                    flags: rtnh.get_rtnh_flags(),
+                });
+                if !more {
+                    break;
+                }
+                buf = &buf[cmp::min(align(len), buf.len())..];
+            }
+        }).is_some()
+    }
+
+    /// get id of the nexthop object the route uses
+    pub fn get_nh_id(&self) -> Option<u32> {
+        self.get_u32(RTA_NH_ID)
+    }
+
+    /// get lightweight tunnel encapsulation
+    pub fn get_encap(&self) -> Option<Encap> {
+        let encap_type = self.with_rta(RTA_ENCAP_TYPE, |rta| {
+            if rta.payload().len() < 2 {
+                return None;
+            }
+            Some(NativeEndian::read_u16(rta.payload()))
+        }).and_then(|type_| type_);
+        encap_type.and_then(|type_| self.with_rta(RTA_ENCAP, |rta| Encap::from_attrs(type_, rta.payload())))
+            .and_then(|encap| encap)
+    }
+
+    /// get route metrics (RTA_METRICS)
+    pub fn get_metrics(&self) -> Option<RouteMetrics> {
+        self.with_rta(RTA_METRICS, |rta| RouteMetrics::from_attrs(rta.payload()))
+    }
+
+    /// get cache info
+    pub fn get_cache_info(&self) -> Option<RouteCacheInfo> {
+        self.with_rta(RTA_CACHEINFO, |rta| {
+            RouteCacheInfoPacket::new(rta.payload()).map(|info| info.from_packet())
+        }).and_then(|info| info)
+    }
+
+    // helper methods
+    fn with_rtmsg<T,F>(&self, cb: F) -> T
+        where F: FnOnce(RtMsgPacket) -> T {
+        cb(RtMsgPacket::new(self.packet.payload()).unwrap())
+    }
+
+    fn with_rta<T,F>(&self, rta_type: u16, cb: F) -> Option<T>
+        where F: FnOnce(RtAttrPacket) -> T {
+        self.with_rtmsg(|rtm| {
+            RtAttrIterator::new(rtm.payload()).find(|rta| rta.get_rta_type() == rta_type).map(cb)
+        })
+    }
+
+    fn get_u32(&self, rta_type: u16) -> Option<u32> {
+        self.with_rta(rta_type, |rta| read_u32(rta.payload())).and_then(|val| val)
+    }
+
+    fn get_ip(&self, rta_type: u16) -> Option<IpAddr> {
+        let family = self.get_family();
+        self.with_rta(rta_type, |rta| ip_from_bytes(family, rta.payload())).and_then(|ip| ip)
+    }
+
+    #[cfg(test)]
+    fn dump_route(msg: NetlinkPacket<'static>) {
+        let route = match Route::from_packet(msg) {
+            Some(route) => route,
+            None => return,
+        };
+        println!("├ route: family {} type {:?} table {} proto {} scope {:?} flags {:?}",
+                 route.get_family(), route.get_type(), route.get_table(),
+                 route.get_protocol(), route.get_scope(), route.get_flags());
+        if let Some((dst, len)) = route.get_dst_prefix() {
+            println!(" ├ DST {}/{}", dst, len);
+        }
+        if let Some((src, len)) = route.get_src_prefix() {
+            println!(" ├ SRC {}/{}", src, len);
+        }
+        if let Some(gateway) = route.get_gateway() {
+            println!(" ├ GATEWAY {}", gateway);
+        }
+        if let Some(prefsrc) = route.get_prefsrc() {
+            println!(" ├ PREFSRC {}", prefsrc);
+        }
+        if let Some(oif) = route.get_oif() {
+            println!(" ├ OUT.IF {}", oif);
+        }
+        if let Some(prio) = route.get_priority() {
+            println!(" ├ PRIO {}", prio);
+        }
+        if let Some(encap) = route.get_encap() {
+            println!(" ├ ENCAP {:?}", encap);
+        }
+        if let Some(id) = route.get_nh_id() {
+            println!(" ├ NH ID {}", id);
+        }
+        if let Some(metrics) = route.get_metrics() {
+            println!(" ├ METRICS {:?}", metrics);
+        }
+        for nexthop in route.get_multipath().unwrap_or(vec![]) {
+            println!(" ├ NEXTHOP dev {} via {:?} weight {} flags {:?}", nexthop.get_ifindex(),
+                     nexthop.get_gateway(), nexthop.get_weight(), nexthop.get_flags());
+        }
+        if let Some(info) = route.get_cache_info() {
+            println!(" ├ CACHE INFO {:?}", info);
+        }
+    }
+}
+
+/// Route operations trait
+pub trait Routes where Self: Read + Write {
+    /// add route, fails if it already exists
+    fn add_route(&mut self, route: RouteBuilder) -> Result<()>;
+    /// add route or replace the existing one
+    fn replace_route(&mut self, route: RouteBuilder) -> Result<()>;
+    /// add route even if there are routes to the same destination
+    fn append_route(&mut self, route: RouteBuilder) -> Result<()>;
+    /// delete route, fields which are not set match any route
+    fn delete_route(&mut self, route: RouteBuilder) -> Result<()>;
+    /// look up route the kernel would use for packets to `dst`, like `ip route get`,
+    /// None if the network is unreachable
+    fn route_get(&mut self, dst: IpAddr, src: Option<IpAddr>, iif: Option<u32>, oif: Option<u32>,
+                 mark: Option<u32>, uid: Option<u32>) -> Result<Option<Route>> {
+        self.route_get_with_flags(dst, src, iif, oif, mark, uid, RtmFlags::new(0))
+    }
+    /// same as `route_get` with request `flags`, e.g. `FIB_MATCH` returns
+    /// the matching FIB entry instead of the resolved route
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+    fn route_get_with_flags(&mut self, dst: IpAddr, src: Option<IpAddr>, iif: Option<u32>, oif: Option<u32>,
+                            mark: Option<u32>, uid: Option<u32>, flags: RtmFlags) -> Result<Option<Route>>;
+}
+
+impl Routes for NetlinkConnection {
+    fn add_route(&mut self, route: RouteBuilder) -> Result<()> {
+        let req = NetlinkRequestBuilder::new(RTM_NEWROUTE, NLM_F_CREATE | NLM_F_EXCL | NLM_F_ACK)
+            .append(try!(route.build())).build();
+        let reader = try!(self.send(req));
+        reader.read_to_end()
+    }
+
+    fn replace_route(&mut self, route: RouteBuilder) -> Result<()> {
+        let req = NetlinkRequestBuilder::new(RTM_NEWROUTE, NLM_F_CREATE | NLM_F_REPLACE | NLM_F_ACK)
+            .append(try!(route.build())).build();
+        let reader = try!(self.send(req));
+        reader.read_to_end()
+    }
+
+    fn append_route(&mut self, route: RouteBuilder) -> Result<()> {
+        let req = NetlinkRequestBuilder::new(RTM_NEWROUTE, NLM_F_CREATE | NLM_F_APPEND | NLM_F_ACK)
+            .append(try!(route.build())).build();
+        let reader = try!(self.send(req));
+        reader.read_to_end()
+    }
+
+    fn delete_route(&mut self, route: RouteBuilder) -> Result<()> {
+        let req = NetlinkRequestBuilder::new(RTM_DELROUTE, NLM_F_ACK)
+            .append(try!(route.build_rtmsg(true))).build();
+        let reader = try!(self.send(req));
+        reader.read_to_end()
+    }
+
+    fn route_get_with_flags(&mut self, dst: IpAddr, src: Option<IpAddr>, iif: Option<u32>, oif: Option<u32>,
+                            mark: Option<u32>, uid: Option<u32>, flags: RtmFlags) -> Result<Option<Route>> {
+        let reader = try!(self.send(route_get_request(dst, src, iif, oif, mark, uid, flags)));
+        let mut found = None;
+        for route in (RoutesIterator { iter: reader.into_iter(), filter: RouteFilter::new() }) {
+            match route {
+                Ok(route) => found = Some(route),
+                Err(ref e) if e.raw_os_error() == Some(libc::ENETUNREACH) => return Ok(None),
+                Err(e) => return Err(e),
             }
         }
+        Ok(found)
+    }
+}
+
+/// Route description for `Routes` operations. Family is taken
+/// from the addresses unless set explicitly.
+pub struct RouteBuilder {
+    family: Option<u8>,
+    dst: Option<(IpAddr, u8)>,
+    gateway: Option<IpAddr>,
+    oif: Option<u32>,
+    table: u32,
+    metric: Option<u32>,
+    scope: Option<Scope>,
+    protocol: Option<u8>,
+    type_: Option<RtmType>,
+    prefsrc: Option<IpAddr>,
+    mark: Option<u32>,
+    multipath: Vec<NextHop>,
+    metrics: Option<RouteMetrics>,
+    nh_id: Option<u32>,
+    encap: Option<Encap>,
+}
+
+impl RouteBuilder {
+    /// Creates unicast route in the main table, without destination
+    /// it is the default route
+    pub fn new() -> Self {
+        RouteBuilder {
+            family: None,
+            dst: None,
+            gateway: None,
+            oif: None,
+            table: RT_TABLE_MAIN,
+            metric: None,
+            scope: None,
+            protocol: None,
+            type_: None,
+            prefsrc: None,
+            mark: None,
+            multipath: vec![],
+            metrics: None,
+            nh_id: None,
+            encap: None,
+        }
+    }
+
+    /// Set address family, needed only for routes without addresses
+    pub fn set_family(mut self, family: u8) -> Self {
+        self.family = Some(family);
+        self
+    }
+
+    /// Set destination prefix
+    pub fn set_dst(mut self, dst: IpAddr, prefix_len: u8) -> Self {
+        self.dst = Some((dst, prefix_len));
+        self
+    }
+
+    pub fn set_gateway(mut self, gateway: IpAddr) -> Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    /// Set output interface index
+    pub fn set_oif(mut self, index: u32) -> Self {
+        self.oif = Some(index);
+        self
+    }
+
+    pub fn set_table(mut self, table: u32) -> Self {
+        self.table = table;
+        self
+    }
+
+    /// Set metric (RTA_PRIORITY)
+    pub fn set_metric(mut self, metric: u32) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    /// Set scope, by default it's `Link` for routes without gateway,
+    /// `Host` for local routes and `Universe` for the rest
+    pub fn set_scope(mut self, scope: Scope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    /// Set protocol, `RTPROT_BOOT` by default
+    pub fn set_protocol(mut self, protocol: u8) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Set route type, `UNICAST` by default
+    pub fn set_type(mut self, type_: RtmType) -> Self {
+        self.type_ = Some(type_);
+        self
+    }
+
+    /// Set preferred source address
+    pub fn set_prefsrc(mut self, prefsrc: IpAddr) -> Self {
+        self.prefsrc = Some(prefsrc);
+        self
+    }
+
+    /// Set firewall mark
+    pub fn set_mark(mut self, mark: u32) -> Self {
+        self.mark = Some(mark);
+        self
+    }
+
+    /// Add nexthop of a multipath route
+    pub fn add_nexthop(mut self, nexthop: NextHop) -> Self {
+        self.multipath.push(nexthop);
+        self
+    }
+
+    /// Use nexthop object `id` instead of gateway and output interface
+    pub fn set_nh_id(mut self, id: u32) -> Self {
+        self.nh_id = Some(id);
+        self
+    }
+
+    /// Set lightweight tunnel encapsulation, like `ip route add ... encap`
+    pub fn set_encap(mut self, encap: Encap) -> Self {
+        self.encap = Some(encap);
+        self
+    }
+
+    /// Set route metrics (RTA_METRICS), not to be confused with `set_metric`
+    pub fn set_metrics(mut self, metrics: RouteMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Builds rtmsg, fails if a nexthop weight is 0 or above 256
+    /// or the encapsulation can't be encoded
+    pub fn build(self) -> Result<RtMsgPacket<'static>> {
+        self.build_rtmsg(false)
+    }
+
+    /// Unset fields of a delete request are left zero, so that they match any route
+    fn build_rtmsg(&self, delete: bool) -> Result<RtMsgPacket<'static>> {
+        if self.multipath.iter().any(|nh| nh.weight == 0 || nh.weight > 256) {
+            return Err(Error::InvalidArgument("multipath nexthop weight must be 1 to 256"));
+        }
+        let type_ = self.type_.unwrap_or(if delete { RtmType::UNSPEC } else { RtmType::UNICAST });
+        let scope = match self.scope {
+            Some(scope) => scope,
+            None if delete => Scope::Nowhere,
+            None => match type_ {
+                RtmType::LOCAL | RtmType::NAT => Scope::Host,
+                RtmType::UNICAST if self.gateway.is_none() && self.nh_id.is_none() &&
+                    self.multipath.iter().all(|nh| nh.gateway.is_none()) => Scope::Link,
+                _ => Scope::Universe,
+            },
+        };
+        let protocol = self.protocol.unwrap_or(if delete { RTPROT_UNSPEC } else { RTPROT_BOOT });
+        let mut data = vec![0; MutableRtMsgPacket::minimum_packet_size()];
+        {
+            let mut rtm = MutableRtMsgPacket::new(&mut data).unwrap();
+            rtm.set_rtm_family(self.get_family());
+            rtm.set_rtm_dst_len(self.dst.map(|(_, len)| len).unwrap_or(0));
+            // tables above 255 are passed in RTA_TABLE
+            rtm.set_rtm_table(if self.table < 256 { self.table as u8 } else { RT_TABLE_UNSPEC as u8 });
+            rtm.set_rtm_protocol(protocol);
+            rtm.set_rtm_scope(scope);
+            rtm.set_rtm_type(type_);
+            rtm.set_rtm_flags(RtmFlags::new(0));
+        }
+        if let Some((dst, _)) = self.dst {
+            append_rta(&mut data, RTA_DST, &ip_octets(&dst));
+        }
+        if let Some(gateway) = self.gateway {
+            append_rta(&mut data, RTA_GATEWAY, &ip_octets(&gateway));
+        }
+        if let Some(oif) = self.oif {
+            append_rta_u32(&mut data, RTA_OIF, oif);
+        }
+        if self.table >= 256 {
+            append_rta_u32(&mut data, RTA_TABLE, self.table);
+        }
+        if let Some(metric) = self.metric {
+            append_rta_u32(&mut data, RTA_PRIORITY, metric);
+        }
+        if let Some(prefsrc) = self.prefsrc {
+            append_rta(&mut data, RTA_PREFSRC, &ip_octets(&prefsrc));
+        }
+        if let Some(mark) = self.mark {
+            append_rta_u32(&mut data, RTA_MARK, mark);
+        }
+        if !self.multipath.is_empty() {
+            let mut multipath = vec![];
+            for nexthop in &self.multipath {
+                let start = multipath.len();
+                multipath.resize(start + MutableRtNextHopPacket::minimum_packet_size(), 0);
+                if let Some(gateway) = nexthop.gateway {
+                    append_rta(&mut multipath, RTA_GATEWAY, &ip_octets(&gateway));
+                }
+                let len = multipath.len() - start;
+                let mut rtnh = MutableRtNextHopPacket::new(&mut multipath[start..]).unwrap();
+                rtnh.set_rtnh_len(len as u16);
+                rtnh.set_rtnh_flags(nexthop.flags);
+                rtnh.set_rtnh_hops((nexthop.weight - 1) as u8);
+                rtnh.set_rtnh_ifindex(nexthop.ifindex);
+            }
+            append_rta(&mut data, RTA_MULTIPATH, &multipath);
+        }
+        if let Some(ref metrics) = self.metrics {
+            append_rta(&mut data, RTA_METRICS, &metrics.to_attrs());
+        }
+        if let Some(id) = self.nh_id {
+            append_rta_u32(&mut data, RTA_NH_ID, id);
+        }
+        if let Some(ref encap) = self.encap {
+            let mut encap_type = [0; 2];
+            NativeEndian::write_u16(&mut encap_type, encap.get_type());
+            append_rta(&mut data, RTA_ENCAP_TYPE, &encap_type);
+            append_rta(&mut data, RTA_ENCAP, &try!(encap.to_attrs()));
+        }
+        Ok(RtMsgPacket::owned(data).unwrap())
+    }
+
+    fn get_family(&self) -> u8 {
+        if let Some(family) = self.family {
+            return family;
+        }
+        let addr = self.dst.map(|(dst, _)| dst).or(self.gateway).or(self.prefsrc)
+            .or(self.multipath.iter().filter_map(|nh| nh.gateway).next());
+        match addr {
+            Some(IpAddr::V6(_)) => 10 /* AF_INET6 */,
+            _ => 2 /* AF_INET */,
+        }
+    }
+}
+
+/// Route metrics, nested RTA_METRICS attributes. Values are
+/// passed as is, e.g. `rtt` is in milliseconds scaled by 8.
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct RouteMetrics {
+    /// Bitmask of locked metrics, `1 << RTAX_MTU` locks MTU
+    pub lock: u32,
+    pub mtu: Option<u32>,
+    pub window: Option<u32>,
+    pub rtt: Option<u32>,
+    pub rttvar: Option<u32>,
+    pub ssthresh: Option<u32>,
+    pub cwnd: Option<u32>,
+    pub advmss: Option<u32>,
+    pub reordering: Option<u32>,
+    pub hoplimit: Option<u32>,
+    pub initcwnd: Option<u32>,
+    /// `RTAX_FEATURE_*` bits
+    pub features: Option<u32>,
+    pub rto_min: Option<u32>,
+    pub initrwnd: Option<u32>,
+    pub quickack: Option<u32>,
+    /// Congestion control algorithm name, e.g. "cubic"
+    pub cc_algo: Option<String>,
+    pub fastopen_no_cookie: Option<u32>,
+}
+
+impl RouteMetrics {
+    /// Returns whether metric `rtax`, e.g. `RTAX_MTU`, is locked
+    pub fn is_locked(&self, rtax: u16) -> bool {
+        rtax < 32 && self.lock & (1 << rtax) != 0
+    }
+
+    /// Locks metric `rtax` so the kernel doesn't change it, e.g. by PMTU discovery
+    pub fn set_locked(&mut self, rtax: u16) {
+        if rtax < 32 {
+            self.lock |= 1 << rtax;
+        }
+    }
+
+    fn values(&self) -> [(u16, Option<u32>); 15] {
+        [
+            (RTAX_MTU, self.mtu),
+            (RTAX_WINDOW, self.window),
+            (RTAX_RTT, self.rtt),
+            (RTAX_RTTVAR, self.rttvar),
+            (RTAX_SSTHRESH, self.ssthresh),
+            (RTAX_CWND, self.cwnd),
+            (RTAX_ADVMSS, self.advmss),
+            (RTAX_REORDERING, self.reordering),
+            (RTAX_HOPLIMIT, self.hoplimit),
+            (RTAX_INITCWND, self.initcwnd),
+            (RTAX_FEATURES, self.features),
+            (RTAX_RTO_MIN, self.rto_min),
+            (RTAX_INITRWND, self.initrwnd),
+            (RTAX_QUICKACK, self.quickack),
+            (RTAX_FASTOPEN_NO_COOKIE, self.fastopen_no_cookie),
+        ]
+    }
+
+    fn from_attrs(buf: &[u8]) -> RouteMetrics {
+        let mut metrics = RouteMetrics::default();
+        for rta in RtAttrIterator::new(buf) {
+            let payload = rta.payload();
+            if rta.get_rta_type() == RTAX_CC_ALGO {
+                metrics.cc_algo = Some(read_string(payload));
+                continue;
+            }
+            let val = read_u32(payload);
+            match rta.get_rta_type() {
+                RTAX_LOCK => metrics.lock = val.unwrap_or(0),
+                RTAX_MTU => metrics.mtu = val,
+                RTAX_WINDOW => metrics.window = val,
+                RTAX_RTT => metrics.rtt = val,
+                RTAX_RTTVAR => metrics.rttvar = val,
+                RTAX_SSTHRESH => metrics.ssthresh = val,
+                RTAX_CWND => metrics.cwnd = val,
+                RTAX_ADVMSS => metrics.advmss = val,
+                RTAX_REORDERING => metrics.reordering = val,
+                RTAX_HOPLIMIT => metrics.hoplimit = val,
+                RTAX_INITCWND => metrics.initcwnd = val,
+                RTAX_FEATURES => metrics.features = val,
+                RTAX_RTO_MIN => metrics.rto_min = val,
+                RTAX_INITRWND => metrics.initrwnd = val,
+                RTAX_QUICKACK => metrics.quickack = val,
+                RTAX_FASTOPEN_NO_COOKIE => metrics.fastopen_no_cookie = val,
+                _ => {},
+            }
+        }
+        metrics
+    }
+
+    fn to_attrs(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        if self.lock != 0 {
+            append_rta_u32(&mut buf, RTAX_LOCK, self.lock);
+        }
+        for &(rtax, val) in self.values().iter() {
+            if let Some(val) = val {
+                append_rta_u32(&mut buf, rtax, val);
+            }
+        }
+        if let Some(ref name) = self.cc_algo {
+            append_rta_str(&mut buf, RTAX_CC_ALGO, name);
+        }
+        buf
+    }
+}
+
+/// Nexthop of a multipath route
+#[derive(Debug,Clone,PartialEq)]
+pub struct NextHop {
+    ifindex: u32,
+    gateway: Option<IpAddr>,
+    weight: u16,
+    flags: RtnhFlags,
+}
+
+impl NextHop {
+    /// Creates nexthop via interface `ifindex` with weight 1
+    pub fn new(ifindex: u32) -> Self {
+        NextHop {
+            ifindex: ifindex,
+            gateway: None,
+            weight: 1,
+            flags: RtnhFlags::new(0),
+        }
+    }
+
+    pub fn set_gateway(mut self, gateway: IpAddr) -> Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    /// Set weight, 1 to 256, other weights fail when the route is built
+    pub fn set_weight(mut self, weight: u16) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Set flags, e.g. `RTNH_F_ONLINK`
+    pub fn set_flags(mut self, flags: RtnhFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// get output interface index
+    pub fn get_ifindex(&self) -> u32 {
+        self.ifindex
+    }
+
+    /// get gateway address
+    pub fn get_gateway(&self) -> Option<IpAddr> {
+        self.gateway
+    }
+
+    /// get weight, `rtnh_hops` + 1
+    pub fn get_weight(&self) -> u16 {
+        self.weight
+    }
+
+    /// get flags, the kernel reports dead and linkdown nexthops here
+    pub fn get_flags(&self) -> RtnhFlags {
+        self.flags
+    }
+}
+
+/// Asynchronous routes operation trait
+#[cfg(feature = "async")]
+pub trait AsyncRoutes {
+    /// iterate over `family` routes, all families if None
+    fn iter_routes(&self, family: Option<u8>) -> Box<Stream<Item = Route, Error = Error>>;
+}
+
+#[cfg(feature = "async")]
+impl AsyncRoutes for AsyncNetlinkConnection {
+    fn iter_routes(&self, family: Option<u8>) -> Box<Stream<Item = Route, Error = Error>> {
+        let filter = RouteFilter { family: family, ..Default::default() };
+        Box::new(self.request(dump_routes_request(&filter))
+            .take_while(|pkt| Ok(pkt.get_kind() == RTM_NEWROUTE))
+            .map(|pkt| Route { packet: pkt }))
     }
 }
 
 pub struct RoutesIterator<R: Read> {
     iter: NetlinkBufIterator<R>,
+    filter: RouteFilter,
 }
 
-impl<R: Read> Iterator for RoutesIterator<R> {
-    type Item = Route;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.iter.next() {
-            Some(pkt) => {
-                let kind = pkt.get_kind();
-                if kind != RTM_NEWROUTE {
+impl<R: Read> RoutesIterator<R> {
+    /// Same as `next`, but the route is borrowed from the reader's
+    /// buffer instead of being copied. Routes not passing the filter
+    /// are skipped without copying as well.
+    pub fn next_ref(&mut self) -> Option<Result<RouteView>> {
+        let filter = &self.filter;
+        let next = self.iter.next_ref_skipping(|pkt| {
+            pkt.get_kind() == RTM_NEWROUTE &&
+                !filter.matches(&RouteView { packet: NetlinkPacket::new(pkt.packet()).unwrap() })
+        });
+        match next {
+            Some(Ok(pkt)) => {
+                if pkt.get_kind() != RTM_NEWROUTE {
                     return None;
                 }
-                return Some(Route { packet: pkt });
+                Some(Ok(RouteView { packet: pkt }))
             }
+            Some(Err(e)) => Some(Err(e)),
             None => None,
         }
     }
 }
 
+impl<R: Read> Iterator for RoutesIterator<R> {
+    type Item = Result<Route>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_ref().map(|res| res.map(|route| route.into_owned()))
+    }
+}
+
 #[test]
 fn dump_routes() {
-    let mut conn = NetlinkConnection::new();
-    for route in Route::iter_routes(&mut conn) {
-        Route::dump_route(route.packet);
+    let mut conn = NetlinkConnection::new().unwrap();
+    for route in Route::iter_routes(&mut conn, None).unwrap() {
+        Route::dump_route(route.unwrap().packet);
+    }
+}
+
+#[test]
+fn iter_routes_ref() {
+    let mut conn = NetlinkConnection::new().unwrap();
+    let mut iter = Route::iter_routes(&mut conn, Some(2)).unwrap();
+    let mut found = false;
+    while let Some(route) = iter.next_ref() {
+        let route = route.unwrap();
+        assert_eq!(route.get_family(), 2);
+        if route.get_dst() == Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))) {
+            found = true;
+        }
+    }
+    assert!(found);
+}
+
+#[test]
+fn lo_local_route() {
+    use packet::route::link::Links;
+
+    let mut conn = NetlinkConnection::new().unwrap();
+    let lo = conn.get_link_by_name("lo").unwrap().unwrap();
+    let routes = Route::dump_routes(&mut conn, Some(2), 3).unwrap();
+    let route = routes.iter().find(|route| {
+        route.get_table() == RT_TABLE_LOCAL && route.get_dst_len() == 8 &&
+            route.get_dst() == Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0)))
+    }).unwrap();
+    assert_eq!(route.get_family(), 2);
+    assert_eq!(route.get_protocol(), RTPROT_KERNEL);
+    assert_eq!(route.get_oif(), Some(lo.get_index()));
+    assert_eq!(route.get_prefsrc(), Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))));
+    assert!(route.get_gateway().is_none());
+}
+
+#[test]
+fn dump_routes_by_family() {
+    let mut conn = NetlinkConnection::new().unwrap();
+    let routes = Route::dump_routes(&mut conn, Some(10), 3).unwrap();
+    assert!(routes.iter().all(|route| route.get_family() == 10));
+    assert!(routes.iter().filter_map(|route| route.get_dst()).all(|dst| dst.is_ipv6()));
+    let routes = Route::dump_routes(&mut conn, Some(2), 3).unwrap();
+    assert!(routes.iter().all(|route| route.get_family() == 2));
+    assert!(routes.iter().filter_map(|route| route.get_dst()).all(|dst| dst.is_ipv4()));
+}
+
+#[test]
+// CAP_NET_ADMIN needed
+fn dump_routes_by_table() {
+    use packet::route::link::Links;
+    use socket::SockOpt;
+
+    let mut conn = NetlinkConnection::new().unwrap();
+    conn.setsockopt(SockOpt::GetStrictChk, true).unwrap();
+    conn.new_dummy_link("test1493").unwrap();
+    let link = conn.get_link_by_name("test1493").unwrap().unwrap();
+    let index = link.get_index();
+    conn.link_set_up(index).unwrap();
+
+    let dst = IpAddr::V4(Ipv4Addr::new(10, 149, 3, 0));
+    conn.add_route(RouteBuilder::new().set_dst(dst, 24).set_oif(index).set_table(100)).unwrap();
+    let filter = RouteFilter::new().set_family(2).set_table(100).set_oif(index);
+    let routes = Route::dump_routes_filtered(&mut conn, &filter, 3).unwrap();
+    assert!(routes.iter().all(|route| route.get_table() == 100 && route.get_oif() == Some(index)));
+    assert!(routes.iter().any(|route| route.get_dst() == Some(dst)));
+    let routes = Route::iter_routes_filtered(&mut conn, RouteFilter::new().set_table(RT_TABLE_MAIN))
+        .unwrap().collect::<Result<Vec<_>>>().unwrap();
+    assert!(routes.iter().all(|route| route.get_table() == RT_TABLE_MAIN));
+    conn.delete_link(link).unwrap();
+}
+
+#[cfg(test)]
+fn add_encap_route(name: &str, dst: IpAddr, len: u8, encap: Encap) {
+    use packet::route::link::Links;
+
+    let mut conn = NetlinkConnection::new().unwrap();
+    conn.new_dummy_link(name).unwrap();
+    let link = conn.get_link_by_name(name).unwrap().unwrap();
+    let index = link.get_index();
+    conn.link_set_up(index).unwrap();
+    conn.add_route(RouteBuilder::new().set_dst(dst, len).set_oif(index).set_encap(encap.clone())).unwrap();
+    {
+        let family = if dst.is_ipv4() { 2 } else { 10 };
+        let routes = Route::dump_routes(&mut conn, Some(family), 3).unwrap();
+        let route = routes.iter().find(|route| route.get_dst() == Some(dst)).unwrap();
+        assert_eq!(route.get_encap(), Some(encap));
+    }
+    conn.delete_link(link).unwrap();
+}
+
+#[test]
+// CAP_NET_ADMIN and mpls_iptunnel module needed
+fn add_mpls_encap_route() {
+    let encap = Encap::Mpls { labels: vec![100, 200], ttl: None };
+    add_encap_route("test1495", IpAddr::V4(Ipv4Addr::new(10, 149, 5, 0)), 24, encap);
+}
+
+#[test]
+// CAP_NET_ADMIN and IPv6 segment routing needed
+fn add_seg6_encap_route() {
+    use std::net::Ipv6Addr;
+    use packet::route::lwtunnel::Seg6Mode;
+
+    let segments = vec![Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 1), Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 2)];
+    let encap = Encap::Seg6 { mode: Seg6Mode::Encap, segments: segments };
+    add_encap_route("test1496", IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0x149, 0, 0, 0, 0, 0)), 64, encap);
+}
+
+#[test]
+fn route_get_loopback() {
+    use packet::route::link::Links;
+
+    let mut conn = NetlinkConnection::new().unwrap();
+    let lo = conn.get_link_by_name("lo").unwrap().unwrap();
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let route = conn.route_get(localhost, None, None, None, None, None).unwrap().unwrap();
+    assert_eq!(route.get_dst_prefix(), Some((localhost, 32)));
+    assert_eq!(route.get_type(), RtmType::LOCAL);
+    assert_eq!(route.get_oif(), Some(lo.get_index()));
+    assert_eq!(route.get_prefsrc(), Some(localhost));
+
+    let route = conn.route_get_with_flags(localhost, None, None, None, None, None, FIB_MATCH).unwrap().unwrap();
+    assert_eq!(route.get_table(), RT_TABLE_LOCAL);
+    assert_eq!(route.get_dst(), Some(localhost));
+    assert_eq!(route.get_dst_len(), 32);
+}
+
+#[test]
+// CAP_NET_ADMIN needed
+fn add_and_delete_route() {
+    use packet::route::link::Links;
+
+    let mut conn = NetlinkConnection::new().unwrap();
+    conn.new_dummy_link("test1491").unwrap();
+    let link = conn.get_link_by_name("test1491").unwrap().unwrap();
+    let index = link.get_index();
+    conn.link_set_up(index).unwrap();
+
+    let dst = IpAddr::V4(Ipv4Addr::new(10, 149, 0, 0));
+    let route = move || RouteBuilder::new().set_dst(dst, 24).set_oif(index).set_metric(10);
+    conn.add_route(route()).unwrap();
+    assert!(conn.add_route(route()).is_err());
+    let metrics = RouteMetrics { mtu: Some(1400), initcwnd: Some(10), ..Default::default() };
+    conn.replace_route(route().set_protocol(RTPROT_STATIC).set_metrics(metrics)).unwrap();
+    {
+        let routes = Route::dump_routes(&mut conn, Some(2), 3).unwrap();
+        let found = routes.iter().find(|route| route.get_dst() == Some(dst)).unwrap();
+        assert_eq!(found.get_dst_len(), 24);
+        assert_eq!(found.get_oif(), Some(index));
+        assert_eq!(found.get_priority(), Some(10));
+        assert_eq!(found.get_protocol(), RTPROT_STATIC);
+        assert_eq!(found.get_type(), RtmType::UNICAST);
+        let metrics = found.get_metrics().unwrap();
+        assert_eq!(metrics.mtu, Some(1400));
+        assert_eq!(metrics.initcwnd, Some(10));
+    }
+    conn.delete_route(RouteBuilder::new().set_dst(dst, 24)).unwrap();
+    let routes = Route::dump_routes(&mut conn, Some(2), 3).unwrap();
+    assert!(routes.iter().all(|route| route.get_dst() != Some(dst)));
+    conn.delete_link(link).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use packet::route::RtMsg;
+    use std::net::Ipv6Addr;
+
+    // RTM_NEWROUTE dump replies as sent by the kernel on little endian hosts
+
+    // local 127.0.0.1 dev lo table local proto kernel scope host src 127.0.0.1
+    const LOCAL_ROUTE: [u8; 60] = [
+        0x3c, 0x00, 0x00, 0x00, 0x18, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+        0x02, 0x20, 0x00, 0x00, 0xff, 0x02, 0xfe, 0x02, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x0f, 0x00, 0xff, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x01, 0x00, 0x7f, 0x00, 0x00, 0x01,
+        0x08, 0x00, 0x07, 0x00, 0x7f, 0x00, 0x00, 0x01,
+        0x08, 0x00, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00,
+    ];
+
+    // blackhole 10.1.0.0/16 proto static
+    const BLACKHOLE_ROUTE: [u8; 44] = [
+        0x2c, 0x00, 0x00, 0x00, 0x18, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+        0x02, 0x10, 0x00, 0x00, 0xfe, 0x04, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x0f, 0x00, 0xfe, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x01, 0x00, 0x0a, 0x01, 0x00, 0x00,
+    ];
+
+    // unreachable 2001:db8::/32 proto static metric 1024, cloned
+    const UNREACHABLE_ROUTE: [u8; 64] = [
+        0x40, 0x00, 0x00, 0x00, 0x18, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+        0x0a, 0x20, 0x00, 0x00, 0xfe, 0x04, 0x00, 0x07, 0x00, 0x02, 0x00, 0x00,
+        0x08, 0x00, 0x0f, 0x00, 0xfe, 0x00, 0x00, 0x00,
+        0x14, 0x00, 0x01, 0x00, 0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x06, 0x00, 0x00, 0x04, 0x00, 0x00,
+    ];
+
+    // default proto static nexthop via 192.168.1.1 dev 2 nexthop via 192.168.2.1 dev 3 weight 2 onlink
+    const MULTIPATH_ROUTE: [u8; 72] = [
+        0x48, 0x00, 0x00, 0x00, 0x18, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+        0x02, 0x00, 0x00, 0x00, 0xfe, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x0f, 0x00, 0xfe, 0x00, 0x00, 0x00,
+        0x24, 0x00, 0x09, 0x00,
+        0x10, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00, 0xc0, 0xa8, 0x01, 0x01,
+        0x10, 0x00, 0x04, 0x01, 0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00, 0xc0, 0xa8, 0x02, 0x01,
+    ];
+
+    // 10.0.0.0/8 dev 2 proto static scope link mtu lock 1400 initcwnd 10 congctl cubic
+    const METRICS_ROUTE: [u8; 84] = [
+        0x54, 0x00, 0x00, 0x00, 0x18, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+        0x02, 0x08, 0x00, 0x00, 0xfe, 0x04, 0xfd, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x01, 0x00, 0x0a, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x04, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0x28, 0x00, 0x08, 0x00,
+        0x08, 0x00, 0x01, 0x00, 0x04, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x02, 0x00, 0x78, 0x05, 0x00, 0x00,
+        0x08, 0x00, 0x0b, 0x00, 0x0a, 0x00, 0x00, 0x00,
+        0x0a, 0x00, 0x10, 0x00, 0x63, 0x75, 0x62, 0x69, 0x63, 0x00, 0x00, 0x00,
+    ];
+
+    fn parse(data: &[u8]) -> Route {
+        Route::from_packet(NetlinkPacket::owned(data.to_owned()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse_local_route() {
+        let route = parse(&LOCAL_ROUTE);
+        assert_eq!(route.get_family(), 2);
+        assert_eq!(route.get_dst_len(), 32);
+        assert_eq!(route.get_table(), RT_TABLE_LOCAL);
+        assert_eq!(route.get_protocol(), RTPROT_KERNEL);
+        assert_eq!(route.get_scope() as u8, 254);
+        assert_eq!(route.get_type(), RtmType::LOCAL);
+        assert!(route.get_flags().is_empty());
+        assert_eq!(route.get_dst(), Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))));
+        assert_eq!(route.get_prefsrc(), Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))));
+        assert_eq!(route.get_oif(), Some(1));
+    }
+
+    #[test]
+    fn parse_blackhole_route() {
+        let route = parse(&BLACKHOLE_ROUTE);
+        assert_eq!(route.get_type(), RtmType::BLACKHOLE);
+        assert_eq!(route.get_protocol(), RTPROT_STATIC);
+        assert_eq!(route.get_table(), RT_TABLE_MAIN);
+        assert_eq!(route.get_dst(), Some(IpAddr::V4(Ipv4Addr::new(10, 1, 0, 0))));
+        assert_eq!(route.get_dst_len(), 16);
+        assert!(route.get_oif().is_none());
+    }
+
+    #[test]
+    fn parse_unreachable_route() {
+        let route = parse(&UNREACHABLE_ROUTE);
+        assert_eq!(route.get_family(), 10);
+        assert_eq!(route.get_type(), RtmType::UNREACHABLE);
+        assert_eq!(route.get_flags(), CLONED);
+        assert_eq!(route.get_dst(), Some(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0))));
+        assert_eq!(route.get_priority(), Some(1024));
+    }
+
+    #[test]
+    fn rtmsg_round_trip() {
+        for data in &[&LOCAL_ROUTE[..], &BLACKHOLE_ROUTE[..], &UNREACHABLE_ROUTE[..]] {
+            let payload = &data[NetlinkPacket::minimum_packet_size()..];
+            let rtmsg: RtMsg = RtMsgPacket::new(payload).unwrap().from_packet();
+            let mut buf = vec![0; payload.len()];
+            MutableRtMsgPacket::new(&mut buf).unwrap().populate(&rtmsg);
+            assert_eq!(&buf[..], payload);
+        }
+    }
+
+    #[test]
+    fn build_rtmsg() {
+        let mut buf = vec![0; MutableRtMsgPacket::minimum_packet_size()];
+        {
+            let mut rtm = MutableRtMsgPacket::new(&mut buf).unwrap();
+            rtm.set_rtm_family(2);
+            rtm.set_rtm_dst_len(16);
+            rtm.set_rtm_table(RT_TABLE_MAIN as u8);
+            rtm.set_rtm_protocol(RTPROT_STATIC);
+            rtm.set_rtm_scope(Scope::Universe);
+            rtm.set_rtm_type(RtmType::BLACKHOLE);
+            rtm.set_rtm_flags(RtmFlags::new(0));
+        }
+        assert_eq!(&buf[..], &BLACKHOLE_ROUTE[16..28]);
+    }
+
+    #[test]
+    fn parse_multipath_route() {
+        let route = parse(&MULTIPATH_ROUTE);
+        assert!(route.get_dst().is_none());
+        assert!(route.get_gateway().is_none());
+        let nexthops = route.get_multipath().unwrap();
+        assert_eq!(nexthops.len(), 2);
+        assert_eq!(nexthops[0].get_ifindex(), 2);
+        assert_eq!(nexthops[0].get_gateway(), Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1))));
+        assert_eq!(nexthops[0].get_weight(), 1);
+        assert!(nexthops[0].get_flags().is_empty());
+        assert_eq!(nexthops[1].get_ifindex(), 3);
+        assert_eq!(nexthops[1].get_gateway(), Some(IpAddr::V4(Ipv4Addr::new(192, 168, 2, 1))));
+        assert_eq!(nexthops[1].get_weight(), 2);
+        assert_eq!(nexthops[1].get_flags(), RTNH_F_ONLINK);
+    }
+
+    #[test]
+    fn filter_multipath_oif() {
+        let route = parse(&MULTIPATH_ROUTE);
+        assert!(RouteFilter::new().set_oif(3).matches(&route));
+        assert!(!RouteFilter::new().set_oif(4).matches(&route));
+        assert!(RouteFilter::new().set_oif(1).matches(&parse(&LOCAL_ROUTE)));
+        assert!(!RouteFilter::new().set_oif(2).matches(&parse(&LOCAL_ROUTE)));
+    }
+
+    #[test]
+    fn build_multipath_route() {
+        let route = RouteBuilder::new()
+            .set_protocol(RTPROT_STATIC)
+            .add_nexthop(NextHop::new(2).set_gateway(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1))))
+            .add_nexthop(NextHop::new(3).set_gateway(IpAddr::V4(Ipv4Addr::new(192, 168, 2, 1)))
+                         .set_weight(2).set_flags(RTNH_F_ONLINK))
+            .build().unwrap();
+        assert_eq!(route.get_rtm_scope() as u8, Scope::Universe as u8);
+        assert_eq!(route.packet(), &MULTIPATH_ROUTE[16..28].iter().chain(&MULTIPATH_ROUTE[36..])
+                   .cloned().collect::<Vec<_>>()[..]);
+    }
+
+    fn metrics() -> RouteMetrics {
+        let mut metrics = RouteMetrics {
+            mtu: Some(1400),
+            initcwnd: Some(10),
+            cc_algo: Some("cubic".to_owned()),
+            ..Default::default()
+        };
+        metrics.set_locked(RTAX_MTU);
+        metrics
+    }
+
+    #[test]
+    fn parse_route_metrics() {
+        let route = parse(&METRICS_ROUTE);
+        assert_eq!(route.get_oif(), Some(2));
+        let metrics = route.get_metrics().unwrap();
+        assert_eq!(metrics, metrics());
+        assert!(metrics.is_locked(RTAX_MTU));
+        assert!(!metrics.is_locked(RTAX_INITCWND));
+        assert!(parse(&LOCAL_ROUTE).get_metrics().is_none());
+    }
+
+    #[test]
+    fn build_route_metrics() {
+        let route = RouteBuilder::new()
+            .set_dst(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8)
+            .set_oif(2)
+            .set_protocol(RTPROT_STATIC)
+            .set_metrics(metrics())
+            .build().unwrap();
+        assert_eq!(route.packet(), &METRICS_ROUTE[16..]);
+    }
+
+    #[test]
+    fn multipath_weight_range() {
+        let route = |weight| RouteBuilder::new().add_nexthop(NextHop::new(2).set_weight(weight)).build();
+        assert!(route(256).is_ok());
+        assert!(route(0).is_err());
+        assert!(route(257).is_err());
     }
 }
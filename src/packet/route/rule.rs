@@ -1,21 +1,30 @@
 //! Rules operations
-use packet::route::{FibRulePacket,MutableRtMsgPacket,MutableIfInfoPacket,RtAttrIterator,RtAttrPacket,MutableRtAttrPacket};
+use packet::route::{FibRulePacket,MutableFibRulePacket,RtAttrIterator,RtAttrPacket};
+use packet::route::{append_rta,append_rta_str,append_rta_u32,ip_from_bytes,ip_octets,read_string,read_u32};
 use packet::route::link::Link;
+use packet::route::route::RT_TABLE_MAIN;
 use packet::netlink::{MutableNetlinkPacket,NetlinkPacket,NetlinkErrorPacket};
 use packet::netlink::{NLM_F_ACK,NLM_F_REQUEST,NLM_F_DUMP,NLM_F_MATCH,NLM_F_EXCL,NLM_F_CREATE};
 use packet::netlink::{NLMSG_NOOP,NLMSG_ERROR,NLMSG_DONE,NLMSG_OVERRUN};
 use packet::netlink::{NetlinkBufIterator,NetlinkReader,NetlinkRequestBuilder};
 use socket::{NetlinkSocket,NetlinkProtocol};
 use packet::netlink::NetlinkConnection;
+use ::error::Result;
+#[cfg(feature = "async")]
+use packet::async_netlink::AsyncNetlinkConnection;
+#[cfg(feature = "async")]
+use futures::Stream;
+#[cfg(feature = "async")]
+use ::error::Error;
 use pnet::packet::MutablePacket;
 use pnet::packet::Packet;
 use pnet::packet::PacketSize;
 use pnet::util::MacAddr;
 use libc;
 
-use std::net::Ipv4Addr;
-use std::io::{Read,Cursor,self};
-use byteorder::{LittleEndian, BigEndian, ReadBytesExt};
+use std::net::{Ipv4Addr,IpAddr};
+use std::io::{Read,Write};
+use byteorder::{NativeEndian,ByteOrder};
 
 pub const RTM_NEWRULE: u16 = 32;
 pub const RTM_DELRULE: u16 = 33;
@@ -39,55 +48,650 @@ pub const FRA_SUPPRESS_PREFIXLEN: u16 = 14;
 pub const FRA_TABLE: u16 = 15;      /* Extended table id */
 pub const FRA_FWMASK: u16 = 16;     /* mask for netfilter mark */
 pub const FRA_OIFNAME: u16 = 17;
+pub const FRA_PAD: u16 = 18;
+pub const FRA_L3MDEV: u16 = 19;     /* iif or oif is l3mdev goto its table */
+pub const FRA_UID_RANGE: u16 = 20;  /* UID range */
+pub const FRA_PROTOCOL: u16 = 21;   /* Originator of the rule */
+pub const FRA_IP_PROTO: u16 = 22;   /* ip proto */
+pub const FRA_SPORT_RANGE: u16 = 23; /* sport */
+pub const FRA_DPORT_RANGE: u16 = 24; /* dport */
 
+/// Rule action (action field of fib_rule_hdr)
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+#[repr(u8)]
+pub enum RuleAction {
+    UNSPEC = 0,
+    TABLE = 1, // Pass to fixed table
+    GOTO = 2, // Jump to another rule
+    NOP = 3, // No operation
+    BLACKHOLE = 6, // Drop without notification
+    UNREACHABLE = 7, // Drop with ENETUNREACH
+    PROHIBIT = 8, // Drop with EACCES
+}
+
+impl RuleAction {
+    /// Unknown actions are returned as `UNSPEC`
+    pub fn new(val: u8) -> Self {
+        match val {
+            1 => RuleAction::TABLE,
+            2 => RuleAction::GOTO,
+            3 => RuleAction::NOP,
+            6 => RuleAction::BLACKHOLE,
+            7 => RuleAction::UNREACHABLE,
+            8 => RuleAction::PROHIBIT,
+            _ => RuleAction::UNSPEC,
+        }
+    }
+}
+
+bitflags! {
+    /// Rule flags (flags field of fib_rule_hdr)
+    pub flags RuleFlags: u32 {
+        /// Rule can't be deleted
+        const FIB_RULE_PERMANENT = 0x1,
+        /// Selectors are inverted ("not")
+        const FIB_RULE_INVERT = 0x2,
+        /// Goto target doesn't exist
+        const FIB_RULE_UNRESOLVED = 0x4,
+        /// Input interface is missing
+        const FIB_RULE_IIF_DETACHED = 0x8,
+        /// Output interface is missing
+        const FIB_RULE_OIF_DETACHED = 0x10,
+    }
+}
+
+impl RuleFlags {
+    pub fn new(val: u32) -> Self {
+        RuleFlags::from_bits_truncate(val)
+    }
+}
+
+fn dump_rules_request() -> NetlinkPacket<'static> {
+    let mut buf = vec![0; MutableFibRulePacket::minimum_packet_size()];
+    NetlinkRequestBuilder::new(RTM_GETRULE, NLM_F_DUMP)
+        .append({
+            let mut rule = MutableFibRulePacket::new(&mut buf).unwrap();
+            rule.set_family(0 /* AF_UNSPEC */);
+            rule
+        }).build()
+}
+
+/// Routing policy rule, either owned (`Rule`) or borrowed
+/// from a reader's buffer (`RulesIterator::next_ref`)
 #[derive(Debug)]
-pub struct Rule {
-    packet: NetlinkPacket<'static>,
+pub struct RuleView<'a> {
+    packet: NetlinkPacket<'a>,
 }
 
+/// Rule message owning its buffer
+pub type Rule = RuleView<'static>;
+
 impl Rule {
     /// iterate over rules
-    pub fn iter_rules(conn: &mut NetlinkConnection) -> RulesIterator<&mut NetlinkConnection> {
-        let mut buf = vec![0; MutableIfInfoPacket::minimum_packet_size()];
-        let req = NetlinkRequestBuilder::new(RTM_GETRULE, NLM_F_DUMP)
-            .append({
-                let mut ifinfo = MutableIfInfoPacket::new(&mut buf).unwrap();
-                ifinfo.set_family(0 /* AF_UNSPEC */);
-                ifinfo
-            }).build();
-        let mut reply = conn.send(req);
-        RulesIterator { iter: reply.into_iter() }
-    }
-
-    fn dump_rule(msg: NetlinkPacket) {
-        use std::ffi::CStr;
-        if msg.get_kind() != RTM_NEWRULE {
-            return;
-        }
-        //println!("NetLink pkt {:?}", msg);
-        if let Some(rtm) = FibRulePacket::new(&msg.payload()[0..]) {
-            println!("├ rtm: {:?}", rtm);
-            let payload = &rtm.payload()[0..];
-            let iter = RtAttrIterator::new(payload);
-            for rta in iter {
-                match rta.get_rta_type() {
-                    FRA_PRIORITY => {
-                        let mut cur = Cursor::new(rta.payload());
-                        let prio = cur.read_u32::<LittleEndian>().unwrap();
-                        println!(" ├ PRIORITY: {:?}", prio);
-                    },
-                    FRA_SUPPRESS_PREFIXLEN => {
-                        println!(" ├ SUPPRESS PREFIXLEN: {:?}", rta.payload());
-                    },
-                    FRA_TABLE => {
-                        let mut cur = Cursor::new(rta.payload());
-                        let table = cur.read_u32::<LittleEndian>().unwrap();
-                        println!(" ├ TABLE: {:?}", table);
-                    },
-                    _ => println!(" ├ {:?}", rta),
-                }
+    pub fn iter_rules(conn: &mut NetlinkConnection) -> Result<RulesIterator<&mut NetlinkConnection>> {
+        let mut reply = try!(conn.send(dump_rules_request()));
+        Ok(RulesIterator { iter: reply.into_iter() })
+    }
+
+    /// Dump all rules, restarting interrupted dump up to `retries` times
+    pub fn dump_rules(conn: &mut NetlinkConnection, retries: u32) -> Result<Vec<Rule>> {
+        let pkts = try!(conn.dump(dump_rules_request(), retries));
+        Ok(pkts.into_iter()
+            .filter(|pkt| pkt.get_kind() == RTM_NEWRULE)
+            .map(|pkt| Rule { packet: pkt })
+            .collect())
+    }
+}
+
+impl<'a> RuleView<'a> {
+    /// Wraps RTM_NEWRULE or RTM_DELRULE message, e.g. a notification
+    pub fn from_packet(packet: NetlinkPacket<'a>) -> Option<RuleView<'a>> {
+        match packet.get_kind() {
+            RTM_NEWRULE | RTM_DELRULE if FibRulePacket::new(packet.payload()).is_some() => Some(RuleView { packet: packet }),
+            _ => None,
+        }
+    }
+
+    /// Copies the message out of the buffer it is borrowed from
+    pub fn into_owned(self) -> Rule {
+        RuleView { packet: NetlinkPacket::owned(self.packet.packet().to_owned()).unwrap() }
+    }
+
+    /// get address family
+    pub fn get_family(&self) -> u8 {
+        self.with_rule(|rule| rule.get_family())
+    }
+
+    /// get source address
+    pub fn get_src(&self) -> Option<IpAddr> {
+        self.get_ip(FRA_SRC)
+    }
+
+    /// get source prefix length
+    pub fn get_src_len(&self) -> u8 {
+        self.with_rule(|rule| rule.get_src_len())
+    }
+
+    /// get source prefix as (address, length), None if the rule matches any source
+    pub fn get_src_prefix(&self) -> Option<(IpAddr, u8)> {
+        self.get_src().map(|src| (src, self.get_src_len()))
+    }
+
+    /// get destination address
+    pub fn get_dst(&self) -> Option<IpAddr> {
+        self.get_ip(FRA_DST)
+    }
+
+    /// get destination prefix length
+    pub fn get_dst_len(&self) -> u8 {
+        self.with_rule(|rule| rule.get_dst_len())
+    }
+
+    /// get destination prefix as (address, length), None if the rule matches any destination
+    pub fn get_dst_prefix(&self) -> Option<(IpAddr, u8)> {
+        self.get_dst().map(|dst| (dst, self.get_dst_len()))
+    }
+
+    /// get TOS
+    pub fn get_tos(&self) -> u8 {
+        self.with_rule(|rule| rule.get_tos())
+    }
+
+    /// get input interface name
+    pub fn get_iif_name(&self) -> Option<String> {
+        self.get_string(FRA_IFNAME)
+    }
+
+    /// get output interface name
+    pub fn get_oif_name(&self) -> Option<String> {
+        self.get_string(FRA_OIFNAME)
+    }
+
+    /// get firewall mark
+    pub fn get_fwmark(&self) -> Option<u32> {
+        self.get_u32(FRA_FWMARK)
+    }
+
+    /// get firewall mark mask
+    pub fn get_fwmask(&self) -> Option<u32> {
+        self.get_u32(FRA_FWMASK)
+    }
+
+    /// get routing table id, tables above 255 are only in FRA_TABLE
+    pub fn get_table(&self) -> u32 {
+        match self.get_u32(FRA_TABLE) {
+            Some(table) => table,
+            None => self.with_rule(|rule| rule.get_table() as u32),
+        }
+    }
+
+    /// get priority of the rule the `GOTO` action jumps to
+    pub fn get_goto(&self) -> Option<u32> {
+        self.get_u32(FRA_GOTO)
+    }
+
+    /// get priority (preference), lower values are matched first
+    pub fn get_priority(&self) -> u32 {
+        self.get_u32(FRA_PRIORITY).unwrap_or(0)
+    }
+
+    /// get action
+    pub fn get_action(&self) -> RuleAction {
+        self.with_rule(|rule| RuleAction::new(rule.get_action()))
+    }
+
+    /// get flags
+    pub fn get_flags(&self) -> RuleFlags {
+        self.with_rule(|rule| RuleFlags::new(rule.get_flags()))
+    }
+
+    /// get prefix length at or below which routing decisions are ignored
+    pub fn get_suppress_prefixlen(&self) -> Option<u32> {
+        self.get_u32(FRA_SUPPRESS_PREFIXLEN).and_then(|len| if len == !0 { None } else { Some(len) })
+    }
+
+    /// get interface group whose routing decisions are ignored
+    pub fn get_suppress_ifgroup(&self) -> Option<u32> {
+        self.get_u32(FRA_SUPPRESS_IFGROUP).and_then(|group| if group == !0 { None } else { Some(group) })
+    }
+
+    /// Returns whether the table is taken from the l3mdev (VRF) device of the packet
+    pub fn is_l3mdev(&self) -> bool {
+        self.with_rta(FRA_L3MDEV, |rta| rta.payload().first() == Some(&1)).unwrap_or(false)
+    }
+
+    /// get UID range as (start, end), inclusive
+    pub fn get_uid_range(&self) -> Option<(u32, u32)> {
+        self.with_rta(FRA_UID_RANGE, |rta| {
+            let buf = rta.payload();
+            if buf.len() < 8 {
+                return None;
+            }
+            Some((NativeEndian::read_u32(&buf[0..4]), NativeEndian::read_u32(&buf[4..8])))
+        }).and_then(|range| range)
+    }
+
+    /// get protocol which installed the rule, e.g. `RTPROT_KERNEL`
+    pub fn get_protocol(&self) -> u8 {
+        self.with_rta(FRA_PROTOCOL, |rta| rta.payload().first().cloned()).and_then(|proto| proto).unwrap_or(0)
+    }
+
+    /// get IP protocol, e.g. `libc::IPPROTO_TCP`
+    pub fn get_ip_proto(&self) -> Option<u8> {
+        self.with_rta(FRA_IP_PROTO, |rta| rta.payload().first().cloned()).and_then(|proto| proto)
+    }
+
+    /// get source port range as (start, end), inclusive
+    pub fn get_sport_range(&self) -> Option<(u16, u16)> {
+        self.get_port_range(FRA_SPORT_RANGE)
+    }
+
+    /// get destination port range as (start, end), inclusive
+    pub fn get_dport_range(&self) -> Option<(u16, u16)> {
+        self.get_port_range(FRA_DPORT_RANGE)
+    }
+
+    fn get_port_range(&self, rta_type: u16) -> Option<(u16, u16)> {
+        self.with_rta(rta_type, |rta| {
+            let buf = rta.payload();
+            if buf.len() < 4 {
+                return None;
             }
+            Some((NativeEndian::read_u16(&buf[0..2]), NativeEndian::read_u16(&buf[2..4])))
+        }).and_then(|range| range)
+    }
+
+    fn with_rule<T,F>(&self, cb: F) -> T
+        where F: Fn(FibRulePacket) -> T {
+        cb(FibRulePacket::new(self.packet.payload()).unwrap())
+    }
+
+    fn with_rta<T,F>(&self, rta_type: u16, cb: F) -> Option<T>
+        where F: Fn(RtAttrPacket) -> T {
+        self.with_rule(|rule| {
+            RtAttrIterator::new(rule.payload()).find(|rta| rta.get_rta_type() == rta_type).map(|rta| cb(rta))
+        })
+    }
+
+    fn get_u32(&self, rta_type: u16) -> Option<u32> {
+        self.with_rta(rta_type, |rta| read_u32(rta.payload())).and_then(|val| val)
+    }
+
+    fn get_ip(&self, rta_type: u16) -> Option<IpAddr> {
+        let family = self.get_family();
+        self.with_rta(rta_type, |rta| ip_from_bytes(family, rta.payload())).and_then(|ip| ip)
+    }
+
+    fn get_string(&self, rta_type: u16) -> Option<String> {
+        self.with_rta(rta_type, |rta| read_string(rta.payload()))
+    }
+
+    #[cfg(test)]
+    fn dump_rule(msg: NetlinkPacket<'static>) {
+        let rule = match Rule::from_packet(msg) {
+            Some(rule) => rule,
+            None => return,
+        };
+        println!("├ rule: family {} priority {} action {:?} table {} flags {:?}",
+                 rule.get_family(), rule.get_priority(), rule.get_action(),
+                 rule.get_table(), rule.get_flags());
+        if let Some((src, len)) = rule.get_src_prefix() {
+            println!(" ├ FROM {}/{}", src, len);
+        }
+        if let Some((dst, len)) = rule.get_dst_prefix() {
+            println!(" ├ TO {}/{}", dst, len);
+        }
+        if let Some(name) = rule.get_iif_name() {
+            println!(" ├ IIF {}", name);
+        }
+        if let Some(name) = rule.get_oif_name() {
+            println!(" ├ OIF {}", name);
+        }
+        if let Some(mark) = rule.get_fwmark() {
+            println!(" ├ FWMARK {:#x}/{:#x}", mark, rule.get_fwmask().unwrap_or(!0));
+        }
+        if let Some(goto) = rule.get_goto() {
+            println!(" ├ GOTO {}", goto);
+        }
+        if rule.is_l3mdev() {
+            println!(" ├ L3MDEV");
+        }
+        if let Some((start, end)) = rule.get_uid_range() {
+            println!(" ├ UIDRANGE {}-{}", start, end);
+        }
+        if let Some(proto) = rule.get_ip_proto() {
+            println!(" ├ IPPROTO {}", proto);
+        }
+        if let Some((start, end)) = rule.get_sport_range() {
+            println!(" ├ SPORT {}-{}", start, end);
+        }
+        if let Some((start, end)) = rule.get_dport_range() {
+            println!(" ├ DPORT {}-{}", start, end);
+        }
+        if let Some(len) = rule.get_suppress_prefixlen() {
+            println!(" ├ SUPPRESS PREFIXLEN {}", len);
+        }
+        if let Some(group) = rule.get_suppress_ifgroup() {
+            println!(" ├ SUPPRESS IFGROUP {}", group);
+        }
+    }
+}
+
+/// Rules operation trait
+pub trait Rules where Self: Read + Write {
+    /// add rule, fails if the same rule exists
+    fn add_rule(&mut self, rule: RuleBuilder) -> Result<()>;
+    /// delete the first rule matching all fields which are set
+    fn delete_rule(&mut self, rule: RuleBuilder) -> Result<()>;
+}
+
+impl Rules for NetlinkConnection {
+    fn add_rule(&mut self, rule: RuleBuilder) -> Result<()> {
+        let req = NetlinkRequestBuilder::new(RTM_NEWRULE, NLM_F_CREATE | NLM_F_EXCL | NLM_F_ACK)
+            .append(rule.build()).build();
+        let reader = try!(self.send(req));
+        reader.read_to_end()
+    }
+
+    fn delete_rule(&mut self, rule: RuleBuilder) -> Result<()> {
+        let req = NetlinkRequestBuilder::new(RTM_DELRULE, NLM_F_ACK)
+            .append(rule.build_rule(true)).build();
+        let reader = try!(self.send(req));
+        reader.read_to_end()
+    }
+}
+
+/// Rule description for `Rules` operations. Family is taken
+/// from the addresses unless set explicitly.
+pub struct RuleBuilder {
+    family: Option<u8>,
+    priority: Option<u32>,
+    action: Option<RuleAction>,
+    table: Option<u32>,
+    goto: Option<u32>,
+    src: Option<(IpAddr, u8)>,
+    dst: Option<(IpAddr, u8)>,
+    tos: u8,
+    iif_name: Option<String>,
+    oif_name: Option<String>,
+    fwmark: Option<u32>,
+    fwmask: Option<u32>,
+    suppress_prefixlen: Option<u32>,
+    suppress_ifgroup: Option<u32>,
+    flags: RuleFlags,
+    l3mdev: bool,
+    uid_range: Option<(u32, u32)>,
+    protocol: Option<u8>,
+    ip_proto: Option<u8>,
+    sport_range: Option<(u16, u16)>,
+    dport_range: Option<(u16, u16)>,
+}
+
+impl RuleBuilder {
+    /// Creates rule looking up the main table for any packet
+    pub fn new() -> Self {
+        RuleBuilder {
+            family: None,
+            priority: None,
+            action: None,
+            table: None,
+            goto: None,
+            src: None,
+            dst: None,
+            tos: 0,
+            iif_name: None,
+            oif_name: None,
+            fwmark: None,
+            fwmask: None,
+            suppress_prefixlen: None,
+            suppress_ifgroup: None,
+            flags: RuleFlags::new(0),
+            l3mdev: false,
+            uid_range: None,
+            protocol: None,
+            ip_proto: None,
+            sport_range: None,
+            dport_range: None,
+        }
+    }
+
+    pub fn set_family(mut self, family: u8) -> Self {
+        self.family = Some(family);
+        self
+    }
+
+    /// Set priority, the kernel picks one if not set
+    pub fn set_priority(mut self, priority: u32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Set action, `TABLE` by default
+    pub fn set_action(mut self, action: RuleAction) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    /// Set table looked up by `TABLE` action
+    pub fn set_table(mut self, table: u32) -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    /// Jump to rule with priority `priority`, sets `GOTO` action
+    pub fn set_goto(mut self, priority: u32) -> Self {
+        self.goto = Some(priority);
+        self.action = Some(RuleAction::GOTO);
+        self
+    }
+
+    pub fn set_src(mut self, src: IpAddr, prefix_len: u8) -> Self {
+        self.src = Some((src, prefix_len));
+        self
+    }
+
+    pub fn set_dst(mut self, dst: IpAddr, prefix_len: u8) -> Self {
+        self.dst = Some((dst, prefix_len));
+        self
+    }
+
+    pub fn set_tos(mut self, tos: u8) -> Self {
+        self.tos = tos;
+        self
+    }
+
+    /// Match packets received on interface `name`
+    pub fn set_iif_name(mut self, name: &str) -> Self {
+        self.iif_name = Some(name.to_owned());
+        self
+    }
+
+    /// Match packets sent on interface `name`, e.g. by sockets bound to it
+    pub fn set_oif_name(mut self, name: &str) -> Self {
+        self.oif_name = Some(name.to_owned());
+        self
+    }
+
+    /// Match firewall `mark` under `mask`
+    pub fn set_fwmark(mut self, mark: u32, mask: Option<u32>) -> Self {
+        self.fwmark = Some(mark);
+        self.fwmask = mask;
+        self
+    }
+
+    /// Ignore routing decisions with prefix length `len` or less
+    pub fn set_suppress_prefixlen(mut self, len: u32) -> Self {
+        self.suppress_prefixlen = Some(len);
+        self
+    }
+
+    /// Ignore routing decisions via interfaces in `group`
+    pub fn set_suppress_ifgroup(mut self, group: u32) -> Self {
+        self.suppress_ifgroup = Some(group);
+        self
+    }
+
+    /// Invert selectors ("not")
+    pub fn set_invert(mut self) -> Self {
+        self.flags = self.flags | FIB_RULE_INVERT;
+        self
+    }
+
+    /// Look up the table of the l3mdev (VRF) device the packet
+    /// is received or sent on, no table may be set
+    pub fn set_l3mdev(mut self) -> Self {
+        self.l3mdev = true;
+        self
+    }
+
+    /// Match sockets owned by UIDs `start` to `end`, inclusive
+    pub fn set_uid_range(mut self, start: u32, end: u32) -> Self {
+        self.uid_range = Some((start, end));
+        self
+    }
+
+    /// Set protocol which installed the rule, e.g. `RTPROT_STATIC`
+    pub fn set_protocol(mut self, protocol: u8) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Match IP protocol, e.g. `libc::IPPROTO_UDP`
+    pub fn set_ip_proto(mut self, ip_proto: u8) -> Self {
+        self.ip_proto = Some(ip_proto);
+        self
+    }
+
+    /// Match source ports `start` to `end`, inclusive
+    pub fn set_sport_range(mut self, start: u16, end: u16) -> Self {
+        self.sport_range = Some((start, end));
+        self
+    }
+
+    /// Match destination ports `start` to `end`, inclusive
+    pub fn set_dport_range(mut self, start: u16, end: u16) -> Self {
+        self.dport_range = Some((start, end));
+        self
+    }
+
+    pub fn build(self) -> FibRulePacket<'static> {
+        self.build_rule(false)
+    }
+
+    /// Deleted rule is matched only by fields which are set
+    fn build_rule(&self, delete: bool) -> FibRulePacket<'static> {
+        let table = match self.table {
+            None if !delete && !self.l3mdev &&
+                self.action.unwrap_or(RuleAction::TABLE) == RuleAction::TABLE => Some(RT_TABLE_MAIN),
+            table => table,
+        };
+        let action = match self.action {
+            Some(action) => action,
+            None if delete => RuleAction::UNSPEC,
+            None => RuleAction::TABLE,
+        };
+        let mut data = vec![0; MutableFibRulePacket::minimum_packet_size()];
+        {
+            let mut rule = MutableFibRulePacket::new(&mut data).unwrap();
+            rule.set_family(self.get_family());
+            rule.set_dst_len(self.dst.map(|(_, len)| len).unwrap_or(0));
+            rule.set_src_len(self.src.map(|(_, len)| len).unwrap_or(0));
+            rule.set_tos(self.tos);
+            rule.set_table(match table {
+                Some(table) if table < 256 => table as u8,
+                _ => 0,
+            });
+            rule.set_action(action as u8);
+            rule.set_flags(self.flags.bits());
+        }
+        if let Some(table) = table {
+            append_rta_u32(&mut data, FRA_TABLE, table);
+        }
+        if let Some(priority) = self.priority {
+            append_rta_u32(&mut data, FRA_PRIORITY, priority);
+        }
+        if let Some(goto) = self.goto {
+            append_rta_u32(&mut data, FRA_GOTO, goto);
+        }
+        if let Some((src, _)) = self.src {
+            append_rta(&mut data, FRA_SRC, &ip_octets(&src));
+        }
+        if let Some((dst, _)) = self.dst {
+            append_rta(&mut data, FRA_DST, &ip_octets(&dst));
         }
+        if let Some(ref name) = self.iif_name {
+            append_rta_str(&mut data, FRA_IFNAME, name);
+        }
+        if let Some(ref name) = self.oif_name {
+            append_rta_str(&mut data, FRA_OIFNAME, name);
+        }
+        if let Some(mark) = self.fwmark {
+            append_rta_u32(&mut data, FRA_FWMARK, mark);
+        }
+        if let Some(mask) = self.fwmask {
+            append_rta_u32(&mut data, FRA_FWMASK, mask);
+        }
+        if let Some(len) = self.suppress_prefixlen {
+            append_rta_u32(&mut data, FRA_SUPPRESS_PREFIXLEN, len);
+        }
+        if let Some(group) = self.suppress_ifgroup {
+            append_rta_u32(&mut data, FRA_SUPPRESS_IFGROUP, group);
+        }
+        if self.l3mdev {
+            append_rta(&mut data, FRA_L3MDEV, &[1]);
+        }
+        if let Some((start, end)) = self.uid_range {
+            let mut range = [0; 8];
+            NativeEndian::write_u32(&mut range[0..4], start);
+            NativeEndian::write_u32(&mut range[4..8], end);
+            append_rta(&mut data, FRA_UID_RANGE, &range);
+        }
+        if let Some(protocol) = self.protocol {
+            append_rta(&mut data, FRA_PROTOCOL, &[protocol]);
+        }
+        if let Some(ip_proto) = self.ip_proto {
+            append_rta(&mut data, FRA_IP_PROTO, &[ip_proto]);
+        }
+        if let Some(range) = self.sport_range {
+            append_rta_port_range(&mut data, FRA_SPORT_RANGE, range);
+        }
+        if let Some(range) = self.dport_range {
+            append_rta_port_range(&mut data, FRA_DPORT_RANGE, range);
+        }
+        FibRulePacket::owned(data).unwrap()
+    }
+
+    fn get_family(&self) -> u8 {
+        if let Some(family) = self.family {
+            return family;
+        }
+        match self.src.or(self.dst) {
+            Some((IpAddr::V6(_), _)) => 10 /* AF_INET6 */,
+            _ => 2 /* AF_INET */,
+        }
+    }
+}
+
+fn append_rta_port_range(buf: &mut Vec<u8>, rta_type: u16, (start, end): (u16, u16)) {
+    let mut range = [0; 4];
+    NativeEndian::write_u16(&mut range[0..2], start);
+    NativeEndian::write_u16(&mut range[2..4], end);
+    append_rta(buf, rta_type, &range);
+}
+
+/// Asynchronous rules operation trait
+#[cfg(feature = "async")]
+pub trait AsyncRules {
+    /// iterate over rules
+    fn iter_rules(&self) -> Box<Stream<Item = Rule, Error = Error>>;
+}
+
+#[cfg(feature = "async")]
+impl AsyncRules for AsyncNetlinkConnection {
+    fn iter_rules(&self) -> Box<Stream<Item = Rule, Error = Error>> {
+        Box::new(self.request(dump_rules_request())
+            .take_while(|pkt| Ok(pkt.get_kind() == RTM_NEWRULE))
+            .map(|pkt| Rule { packet: pkt }))
     }
 }
 
@@ -96,17 +700,26 @@ pub struct RulesIterator<R: Read> {
 }
 
 impl<R: Read> Iterator for RulesIterator<R> {
-    type Item = Rule;
+    type Item = Result<Rule>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.iter.next() {
-            Some(pkt) => {
+        self.next_ref().map(|res| res.map(|rule| rule.into_owned()))
+    }
+}
+
+impl<R: Read> RulesIterator<R> {
+    /// Same as `next`, but the rule is borrowed from the reader's
+    /// buffer instead of being copied
+    pub fn next_ref(&mut self) -> Option<Result<RuleView>> {
+        match self.iter.next_ref_skipping(|_| false) {
+            Some(Ok(pkt)) => {
                 let kind = pkt.get_kind();
                 if kind != RTM_NEWRULE {
                     return None;
                 }
-                return Some(Rule { packet: pkt });
+                return Some(Ok(RuleView { packet: pkt }));
             },
+            Some(Err(e)) => Some(Err(e)),
             None => None,
         }
     }
@@ -114,8 +727,142 @@ impl<R: Read> Iterator for RulesIterator<R> {
 
 #[test]
 fn dump_rules() {
-    let mut conn = NetlinkConnection::new();
-    for rule in Rule::iter_rules(&mut conn) {
-        Rule::dump_rule(rule.packet);
+    let mut conn = NetlinkConnection::new().unwrap();
+    for rule in Rule::iter_rules(&mut conn).unwrap() {
+        Rule::dump_rule(rule.unwrap().packet);
+    }
+}
+
+#[test]
+fn iter_rules_ref() {
+    let mut conn = NetlinkConnection::new().unwrap();
+    let mut rules = Rule::iter_rules(&mut conn).unwrap();
+    let mut found = false;
+    while let Some(rule) = rules.next_ref() {
+        // default rule looking up the local table
+        if rule.unwrap().get_table() == 255 {
+            found = true;
+        }
+    }
+    assert!(found);
+}
+
+#[test]
+// CAP_NET_ADMIN needed
+fn add_and_delete_rule() {
+    let mut conn = NetlinkConnection::new().unwrap();
+    let src = IpAddr::V4(Ipv4Addr::new(10, 149, 7, 0));
+    let rule = || RuleBuilder::new().set_priority(1497).set_src(src, 24).set_table(1497).set_fwmark(0x1497, Some(0xffff));
+    conn.add_rule(rule()).unwrap();
+    assert!(conn.add_rule(rule()).is_err());
+    {
+        let rules = Rule::dump_rules(&mut conn, 3).unwrap();
+        let found = rules.iter().find(|rule| rule.get_priority() == 1497).unwrap();
+        assert_eq!(found.get_family(), 2);
+        assert_eq!(found.get_src_prefix(), Some((src, 24)));
+        assert_eq!(found.get_table(), 1497);
+        assert_eq!(found.get_action(), RuleAction::TABLE);
+        assert_eq!(found.get_fwmark(), Some(0x1497));
+        assert_eq!(found.get_fwmask(), Some(0xffff));
+        assert!(found.get_dst().is_none());
+    }
+    conn.delete_rule(RuleBuilder::new().set_priority(1497)).unwrap();
+    let rules = Rule::dump_rules(&mut conn, 3).unwrap();
+    assert!(rules.iter().all(|rule| rule.get_priority() != 1497));
+}
+
+#[test]
+// CAP_NET_ADMIN needed, Linux 4.17+
+fn add_uid_and_port_rule() {
+    let mut conn = NetlinkConnection::new().unwrap();
+    let rule = || RuleBuilder::new().set_priority(1498).set_table(1498)
+        .set_uid_range(1498, 1499).set_ip_proto(17 /* IPPROTO_UDP */).set_dport_range(53, 53);
+    conn.add_rule(rule()).unwrap();
+    {
+        let rules = Rule::dump_rules(&mut conn, 3).unwrap();
+        let found = rules.iter().find(|rule| rule.get_priority() == 1498).unwrap();
+        assert_eq!(found.get_uid_range(), Some((1498, 1499)));
+        assert_eq!(found.get_ip_proto(), Some(17));
+        assert_eq!(found.get_dport_range(), Some((53, 53)));
+        assert!(found.get_sport_range().is_none());
+    }
+    conn.delete_rule(rule()).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv6Addr;
+
+    fn parse(rule: FibRulePacket<'static>) -> Rule {
+        let pkt = NetlinkRequestBuilder::new(RTM_NEWRULE, NLM_F_ACK).append(rule).build();
+        Rule::from_packet(pkt).unwrap()
+    }
+
+    #[test]
+    fn build_default_rule() {
+        let rule = parse(RuleBuilder::new().build());
+        assert_eq!(rule.get_family(), 2);
+        assert_eq!(rule.get_action(), RuleAction::TABLE);
+        assert_eq!(rule.get_table(), RT_TABLE_MAIN);
+        assert!(rule.get_src().is_none());
+    }
+
+    #[test]
+    fn build_rule_round_trip() {
+        let dst = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0));
+        let rule = parse(RuleBuilder::new()
+                         .set_priority(100)
+                         .set_dst(dst, 32)
+                         .set_iif_name("eth0")
+                         .set_oif_name("vrf-blue")
+                         .set_table(1000)
+                         .set_suppress_prefixlen(0)
+                         .set_invert()
+                         .build());
+        assert_eq!(rule.get_family(), 10);
+        assert_eq!(rule.get_priority(), 100);
+        assert_eq!(rule.get_dst_prefix(), Some((dst, 32)));
+        assert_eq!(rule.get_iif_name(), Some("eth0".to_owned()));
+        assert_eq!(rule.get_oif_name(), Some("vrf-blue".to_owned()));
+        assert_eq!(rule.get_table(), 1000);
+        assert_eq!(rule.get_suppress_prefixlen(), Some(0));
+        assert!(rule.get_suppress_ifgroup().is_none());
+        assert_eq!(rule.get_flags(), FIB_RULE_INVERT);
+    }
+
+    #[test]
+    fn build_goto_rule() {
+        let rule = parse(RuleBuilder::new().set_goto(200).build());
+        assert_eq!(rule.get_action(), RuleAction::GOTO);
+        assert_eq!(rule.get_goto(), Some(200));
+        assert_eq!(rule.get_table(), 0);
+    }
+
+    #[test]
+    fn build_selectors_round_trip() {
+        use packet::route::route::RTPROT_STATIC;
+
+        let rule = parse(RuleBuilder::new()
+                         .set_uid_range(1000, 1999)
+                         .set_protocol(RTPROT_STATIC)
+                         .set_ip_proto(6 /* IPPROTO_TCP */)
+                         .set_sport_range(1024, 65535)
+                         .set_dport_range(443, 443)
+                         .build());
+        assert_eq!(rule.get_uid_range(), Some((1000, 1999)));
+        assert_eq!(rule.get_protocol(), RTPROT_STATIC);
+        assert_eq!(rule.get_ip_proto(), Some(6));
+        assert_eq!(rule.get_sport_range(), Some((1024, 65535)));
+        assert_eq!(rule.get_dport_range(), Some((443, 443)));
+        assert!(!rule.is_l3mdev());
+    }
+
+    #[test]
+    fn build_l3mdev_rule() {
+        let rule = parse(RuleBuilder::new().set_l3mdev().build());
+        assert!(rule.is_l3mdev());
+        assert_eq!(rule.get_action(), RuleAction::TABLE);
+        assert_eq!(rule.get_table(), 0);
     }
 }
@@ -2,10 +2,17 @@
 extern crate libc;
 
 use libc::{c_int,c_void};
-use libc::{socket,bind,send,recvfrom,setsockopt,getsockopt};
+use libc::{socket,bind,send,recvfrom,setsockopt,getsockopt,getsockname};
 use std::os::unix::io::{AsRawFd,RawFd};
-use std::io::{Error,Result};
+use std::io::{Error,ErrorKind,Result};
 use std::io::Read;
+use std::cmp;
+use std::mem;
+use std::time::Duration;
+#[cfg(feature = "mio")]
+use mio::{Evented,Poll,PollOpt,Ready,Token};
+#[cfg(feature = "mio")]
+use mio::unix::EventedFd;
 
 mod ffi {
 	use libc::{c_int, sa_family_t, c_short};
@@ -16,6 +23,11 @@ mod ffi {
 	pub const SOL_NETLINK: c_int = 270;
 
 	pub const SO_RCVBUF: c_int = 8;
+	pub const SO_RCVTIMEO: c_int = 20;
+	pub const SO_SNDTIMEO: c_int = 21;
+
+	pub const MSG_PEEK: c_int = 2;
+	pub const MSG_TRUNC: c_int = 0x20;
 
 	#[repr(C)]
 	#[derive(Copy,Clone)]
@@ -35,8 +47,21 @@ pub enum SockOpt {
 	PktInfo = 3,
 	BroadcastError = 4,
 	NoEnobufs = 5,
+	CapAck = 10,
+	ExtAck = 11,
+	/// Validate requests strictly and apply dump filters in the kernel
+	GetStrictChk = 12,
 }
 
+bitflags! {
+	/// Flags for socket creation
+	pub flags SockFlags: c_int {
+		/// Reads and writes fail with `WouldBlock` instead of waiting
+		const SOCK_NONBLOCK = libc::SOCK_NONBLOCK,
+		/// Close the socket on exec()
+		const SOCK_CLOEXEC = libc::SOCK_CLOEXEC,
+	}
+}
 
 /// supported protocols
 pub enum NetlinkProtocol {
@@ -67,6 +92,10 @@ pub enum NetlinkProtocol {
 #[derive(Debug)]
 pub struct NetlinkSocket {
 	fd: RawFd,
+	port_id: u32,
+	// rest of a datagram which didn't fit into the buffer passed to read()
+	rbuf: Vec<u8>,
+	rbuf_at: usize,
 }
 
 impl AsRawFd for NetlinkSocket {
@@ -82,22 +111,34 @@ impl Drop for NetlinkSocket {
 }
 
 impl NetlinkSocket {
+	/// Creates socket bound to a port id chosen by the kernel
 	pub fn bind(proto: NetlinkProtocol, groups: u32) -> Result<NetlinkSocket> {
+		NetlinkSocket::bind_port(proto, 0, groups)
+	}
+
+	/// Creates socket bound to `port_id`. Port id must be unique,
+	/// 0 lets the kernel assign one.
+	pub fn bind_port(proto: NetlinkProtocol, port_id: u32, groups: u32) -> Result<NetlinkSocket> {
+		NetlinkSocket::bind_with_flags(proto, port_id, groups, SockFlags::empty())
+	}
+
+	/// Same as `bind_port`, socket is created with `flags`, e.g.
+	/// `SOCK_NONBLOCK | SOCK_CLOEXEC`
+	pub fn bind_with_flags(proto: NetlinkProtocol, port_id: u32, groups: u32, flags: SockFlags) -> Result<NetlinkSocket> {
 		use std::mem::size_of;
 		use std::mem::transmute;
-		use libc::getpid;
 
 		let mut res = unsafe {
-			socket(ffi::PF_NETLINK, ffi::SOCK_DGRAM, proto as i32)
+			socket(ffi::PF_NETLINK, ffi::SOCK_DGRAM | flags.bits(), proto as i32)
 		};
 		if res < 0 {
 			return Err(Error::last_os_error());
 		}
-		let sock = NetlinkSocket { fd: res };
+		let mut sock = NetlinkSocket { fd: res, port_id: 0, rbuf: vec![], rbuf_at: 0 };
 		let mut sockaddr = ffi::sockaddr_nl {
 			nl_family: ffi::PF_NETLINK as u16,
 			nl_pad: 0,
-			nl_pid: unsafe { getpid() } as u32,
+			nl_pid: port_id,
 			nl_groups: groups,
 		};
 		res = unsafe {
@@ -106,9 +147,22 @@ impl NetlinkSocket {
 		if res < 0 {
 			return Err(Error::last_os_error());
 		}
+		let mut addr_len = size_of::<ffi::sockaddr_nl>() as u32;
+		res = unsafe {
+			getsockname(sock.fd, transmute(&mut sockaddr), &mut addr_len)
+		};
+		if res < 0 {
+			return Err(Error::last_os_error());
+		}
+		sock.port_id = sockaddr.nl_pid;
 		Ok(sock)
 	}
 
+	/// Port id the socket is bound to
+	pub fn get_port_id(&self) -> u32 {
+		self.port_id
+	}
+
 	pub fn send(&mut self, buf: &[u8]) -> Result<usize> {
 		use libc::c_void;
 		let len = buf.len();
@@ -121,6 +175,8 @@ impl NetlinkSocket {
 		Ok(res as usize)
 	}
 
+	/// Receives one datagram. If it doesn't fit into `buf`, the
+	/// datagram is lost and error of kind `InvalidData` is returned.
 	pub fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
 		use libc::c_void;
 		use std::ptr::null_mut;
@@ -128,7 +184,28 @@ impl NetlinkSocket {
 
 		let len = buf.len();
 		let res = unsafe {
-			recvfrom(self.fd, buf.as_mut_ptr() as *mut c_void, len, 0, null_mut::<sockaddr>(), null_mut::<u32>())
+			recvfrom(self.fd, buf.as_mut_ptr() as *mut c_void, len, ffi::MSG_TRUNC,
+					 null_mut::<sockaddr>(), null_mut::<u32>())
+		};
+		if res < 0 {
+			return Err(Error::last_os_error());
+		}
+		if res as usize > len {
+			return Err(Error::new(ErrorKind::InvalidData,
+								  format!("netlink datagram truncated: {} of {} bytes received", len, res)));
+		}
+		Ok(res as usize)
+	}
+
+	/// Returns size of the next datagram without removing it from the queue
+	pub fn peek_size(&mut self) -> Result<usize> {
+		use libc::c_void;
+		use std::ptr::null_mut;
+		use libc::sockaddr;
+
+		let res = unsafe {
+			recvfrom(self.fd, null_mut::<c_void>(), 0, ffi::MSG_PEEK | ffi::MSG_TRUNC,
+					 null_mut::<sockaddr>(), null_mut::<u32>())
 		};
 		if res < 0 {
 			return Err(Error::last_os_error());
@@ -136,6 +213,63 @@ impl NetlinkSocket {
 		Ok(res as usize)
 	}
 
+	/// Switches the socket to non-blocking mode, reads and writes
+	/// then fail with `WouldBlock` instead of waiting
+	pub fn set_nonblocking(&mut self, nonblocking: bool) -> Result<()> {
+		let flags = unsafe { libc::fcntl(self.fd, libc::F_GETFL) };
+		if flags < 0 {
+			return Err(Error::last_os_error());
+		}
+		let flags = if nonblocking {
+			flags | libc::O_NONBLOCK
+		} else {
+			flags & !libc::O_NONBLOCK
+		};
+		if unsafe { libc::fcntl(self.fd, libc::F_SETFL, flags) } < 0 {
+			return Err(Error::last_os_error());
+		}
+		Ok(())
+	}
+
+	/// Sets timeout for reads, after it expires reads fail with
+	/// `WouldBlock`. None means reads wait forever.
+	pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+		self.set_timeout(ffi::SO_RCVTIMEO, timeout)
+	}
+
+	/// Sets timeout for writes, None means writes wait forever
+	pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+		self.set_timeout(ffi::SO_SNDTIMEO, timeout)
+	}
+
+	fn set_timeout(&mut self, option: c_int, timeout: Option<Duration>) -> Result<()> {
+		let tv = match timeout {
+			Some(timeout) => {
+				if timeout.as_secs() == 0 && timeout.subsec_nanos() == 0 {
+					return Err(Error::new(ErrorKind::InvalidInput, "zero timeout"));
+				}
+				let mut tv = libc::timeval {
+					tv_sec: cmp::min(timeout.as_secs(), libc::time_t::max_value() as u64) as libc::time_t,
+					tv_usec: (timeout.subsec_nanos() / 1000) as libc::suseconds_t,
+				};
+				// timeouts shorter than 1us must not turn into "wait forever"
+				if tv.tv_sec == 0 && tv.tv_usec == 0 {
+					tv.tv_usec = 1;
+				}
+				tv
+			},
+			None => libc::timeval { tv_sec: 0, tv_usec: 0 },
+		};
+		let res = unsafe {
+			setsockopt(self.fd, ffi::SOL_SOCKET, option, &tv as *const libc::timeval as *const c_void,
+					   mem::size_of::<libc::timeval>() as u32)
+		};
+		if res == -1 {
+			return Err(Error::last_os_error());
+		}
+		Ok(())
+	}
+
 	fn setsockopt_int(&mut self, level: c_int, option: c_int, val: c_int) -> Result<()> {
 		use std::mem;
 		let res = unsafe {
@@ -171,6 +305,17 @@ impl NetlinkSocket {
 		self.setsockopt_int(ffi::SOL_NETLINK, option as c_int, ffi_val)
 	}
 
+	/// Subscribes to multicast group `group`, e.g. `RTNLGRP_LINK`.
+	/// Unlike `groups` mask passed to `bind`, works for groups above 32.
+	pub fn add_membership(&mut self, group: u32) -> Result<()> {
+		self.setsockopt_int(ffi::SOL_NETLINK, SockOpt::AddMembership as c_int, group as c_int)
+	}
+
+	/// Unsubscribes from multicast group `group`
+	pub fn drop_membership(&mut self, group: u32) -> Result<()> {
+		self.setsockopt_int(ffi::SOL_NETLINK, SockOpt::DropMembership as c_int, group as c_int)
+	}
+
 	pub fn setrcvbuf(&mut self, len: c_int) -> Result<()> {
 		self.setsockopt_int(ffi::SOL_SOCKET, ffi::SO_RCVBUF, len)
 	}
@@ -185,7 +330,65 @@ impl NetlinkSocket {
 }
 
 impl Read for NetlinkSocket {
+	/// Reads next datagram. If `buf` is too small, the rest
+	/// of the datagram is returned by subsequent reads.
 	fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-		self.recv(buf)
+		if self.rbuf_at == self.rbuf.len() {
+			let size = try!(self.peek_size());
+			if size <= buf.len() {
+				return self.recv(buf);
+			}
+			let mut rbuf = mem::replace(&mut self.rbuf, vec![]);
+			rbuf.resize(size, 0);
+			let res = self.recv(&mut rbuf);
+			self.rbuf = rbuf;
+			let len = match res {
+				Ok(len) => len,
+				Err(e) => {
+					self.rbuf.clear();
+					self.rbuf_at = 0;
+					return Err(e);
+				},
+			};
+			self.rbuf.truncate(len);
+			self.rbuf_at = 0;
+		}
+		let len = cmp::min(buf.len(), self.rbuf.len() - self.rbuf_at);
+		buf[..len].copy_from_slice(&self.rbuf[self.rbuf_at..self.rbuf_at + len]);
+		self.rbuf_at += len;
+		Ok(len)
+	}
+}
+
+#[cfg(feature = "mio")]
+impl Evented for NetlinkSocket {
+	fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> Result<()> {
+		EventedFd(&self.fd).register(poll, token, interest, opts)
 	}
+
+	fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> Result<()> {
+		EventedFd(&self.fd).reregister(poll, token, interest, opts)
+	}
+
+	fn deregister(&self, poll: &Poll) -> Result<()> {
+		EventedFd(&self.fd).deregister(poll)
+	}
+}
+
+#[test]
+fn bind_two_sockets() {
+	let sock1 = NetlinkSocket::bind(NetlinkProtocol::Route, 0).unwrap();
+	let sock2 = NetlinkSocket::bind(NetlinkProtocol::Route, 0).unwrap();
+	assert!(sock1.get_port_id() != 0);
+	assert!(sock1.get_port_id() != sock2.get_port_id());
+}
+
+#[test]
+fn nonblocking_read_would_block() {
+	let mut sock = NetlinkSocket::bind_with_flags(NetlinkProtocol::Route, 0, 0, SOCK_NONBLOCK | SOCK_CLOEXEC).unwrap();
+	let mut buf = [0; 16];
+	assert_eq!(sock.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+	sock.set_nonblocking(false).unwrap();
+	sock.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+	assert_eq!(sock.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
 }